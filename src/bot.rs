@@ -1,18 +1,39 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
 use std::ops::Deref;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use serenity::async_trait;
 use serenity::client::bridge::gateway::GatewayIntents;
 use serenity::framework::standard::{Args, CommandError, CommandOptions, CommandResult, Reason, StandardFramework};
 use serenity::framework::standard::macros::{check, command, group, hook};
+use serenity::http::AttachmentType;
 use serenity::model::channel::Message;
-use serenity::model::id::RoleId;
+use serenity::model::id::{ChannelId, MessageId, RoleId, UserId};
+use serenity::model::interactions::{Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType};
+use serenity::model::user::User;
 use serenity::prelude::*;
 
-use crate::{Config, interval, Scrapper};
-use crate::scrapper::LoginResult;
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+
+use crate::{Config, interval, session};
+use crate::interval::IntervalState;
+use crate::browser::{FailedPage, PageCapture};
+use crate::config::ConfigSource;
+use crate::login_state::ScrapperStatus;
+use crate::players::PlayerTracker;
+use crate::redact::Redactor;
+use crate::qr_login::QrChallenge;
+use crate::scrape_error::ScrapeError;
+use crate::scrapper::{AuthCodeResult, LoginResult};
+use crate::scrapper_actor::ScrapperHandle;
+use crate::session::LOGIN_BUTTON_ID;
 
 pub struct Bot {
     pub client: Client,
@@ -21,11 +42,51 @@ pub struct Bot {
 struct Handler;
 
 #[async_trait]
-impl EventHandler for Handler {}
+impl EventHandler for Handler {
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let component = match interaction {
+            Interaction::MessageComponent(component) if component.data.custom_id == LOGIN_BUTTON_ID => component,
+            _ => return,
+        };
+
+        let owner_id = {
+            let lock = ctx.data.read().await;
+            lock.get::<Config>().unwrap().owner_id
+        };
+        if component.user.id != owner_id {
+            let res = component.create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::ChannelMessageWithSource).interaction_response_data(|d| {
+                    d.content("Only the owner can log in.").flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+            }).await;
+            if let Err(why) = res {
+                warn!("failed to refuse login button: {:?}", why);
+            }
+            return;
+        }
+
+        if let Err(why) = component.create_interaction_response(&ctx.http, |r| r.kind(InteractionResponseType::DeferredUpdateMessage)).await {
+            warn!("failed to acknowledge login button: {:?}", why);
+        }
+
+        let span = info_span!("command", command = "login_button", user = %component.user.tag(), user_id = component.user.id.0);
+        let start = Instant::now();
+        let res = login_flow(&ctx, component.channel_id, &component.user).instrument(span.clone()).await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+
+        match res {
+            Ok(()) => span.in_scope(|| info!(duration_ms, outcome = "ok", "command finished")),
+            Err(why) => {
+                span.in_scope(|| warn!(duration_ms, outcome = "error", "login from button failed: {:?}", why));
+                let _ = say(&ctx, component.channel_id, format!("Login failed: `{:?}`", why)).await;
+            },
+        }
+    }
+}
 
 
 #[group]
-#[commands(login, stats, logout, start_interval)]
+#[commands(login, stats, logout, start_interval, players, status, config, screenshot)]
 struct General;
 
 #[check]
@@ -67,74 +128,496 @@ async fn owner_check(
     Ok(())
 }
 
+#[hook]
+async fn dynamic_prefix(ctx: &Context, _msg: &Message) -> Option<String> {
+    let lock = ctx.data.read().await;
+    lock.get::<Config>().map(|cfg| cfg.prefix.clone())
+}
+
+/// Runs text through the configured `Redactor` before it gets posted.
+async fn redacted(ctx: &Context, text: &str) -> String {
+    let lock = ctx.data.read().await;
+    lock.get::<Redactor>().unwrap().redact(text)
+}
+
+/// Every message the bot posts goes through here, so secrets are redacted in one place.
+async fn post(ctx: &Context, channel_id: ChannelId, content: impl AsRef<str>, file: Option<(Vec<u8>, String)>) -> Result<Message> {
+    let content = redacted(ctx, content.as_ref()).await;
+
+    let msg = channel_id.send_message(&ctx.http, |m| {
+        if !content.is_empty() {
+            m.content(content);
+        }
+        if let Some((data, filename)) = file {
+            m.add_file(AttachmentType::Bytes { data: Cow::from(data), filename });
+        }
+        m
+    }).await?;
+
+    Ok(msg)
+}
+
+async fn say(ctx: &Context, channel_id: ChannelId, content: impl AsRef<str>) -> Result<Message> {
+    post(ctx, channel_id, content, None).await
+}
+
+/// How long a command can run before its span is assumed orphaned. `after` never runs for a
+/// command that panicked, so its entry would otherwise stay in `CommandSpans` forever.
+const STALE_COMMAND: Duration = Duration::from_secs(60 * 60);
+
+/// The span of a running command, opened in `before` and closed once `after` takes it out.
+pub struct CommandSpan {
+    span: Span,
+    start: Instant,
+}
+
+/// Running commands keyed by the message that invoked them.
+pub struct CommandSpans;
+
+impl TypeMapKey for CommandSpans {
+    type Value = Arc<Mutex<HashMap<MessageId, CommandSpan>>>;
+}
+
+#[hook]
+async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
+    let span = info_span!("command", command = command_name, user = %msg.author.tag(), user_id = msg.author.id.0);
+    span.in_scope(|| debug!("command started"));
+
+    let spans = ctx.data.read().await.get::<CommandSpans>().unwrap().clone();
+    let mut spans = spans.lock().await;
+    spans.retain(|_, running| running.start.elapsed() < STALE_COMMAND);
+    spans.insert(msg.id, CommandSpan { span, start: Instant::now() });
+
+    true
+}
+
+/// The span `before` opened for the command `msg` invoked. Command bodies run inside it, so what
+/// they log, and what the scrapper logs for them, carries the command and user.
+async fn command_span(ctx: &Context, msg: &Message) -> Span {
+    let spans = ctx.data.read().await.get::<CommandSpans>().unwrap().clone();
+    let spans = spans.lock().await;
+    spans.get(&msg.id).map_or_else(Span::none, |running| running.span.clone())
+}
+
+/// Keeps an `anyhow::Error` intact inside serenity's boxed `CommandError`. Converting it directly
+/// boxes anyhow's internal error type, which hides a `ScrapeError` from the `after` hook.
+struct CommandFailure(anyhow::Error);
+
+impl fmt::Display for CommandFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for CommandFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl std::error::Error for CommandFailure {}
+
+fn command_error(why: anyhow::Error) -> CommandError {
+    Box::new(CommandFailure(why))
+}
+
+/// The `ScrapeError` behind a failed command, wherever it is in the chain.
+fn find_scrape_error(why: &CommandError) -> Option<&ScrapeError> {
+    if let Some(failure) = why.downcast_ref::<CommandFailure>() {
+        return ScrapeError::find(&failure.0);
+    }
+
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&**why);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<ScrapeError>() {
+            return Some(e);
+        }
+        source = e.source();
+    }
+    None
+}
+
+/// The reply to a failed command, telling the user what to do when the cause is known.
+fn failure_message(command_name: &str, why: &CommandError) -> String {
+    match find_scrape_error(why) {
+        Some(e) => format!("Command `{}` failed: {}. {}", command_name, e, e.recovery()),
+        None => format!("Command `{}` failed: `{:?}`", command_name, why),
+    }
+}
+
 #[hook]
 async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: CommandResult) {
+    let spans = ctx.data.read().await.get::<CommandSpans>().unwrap().clone();
+    let running = spans.lock().await.remove(&msg.id);
+    let (span, duration_ms) = match running {
+        Some(running) => (running.span, Some(running.start.elapsed().as_millis() as u64)),
+        None => (info_span!("command", command = command_name, user = %msg.author.tag(), user_id = msg.author.id.0), None),
+    };
+
     match command_result {
-        Ok(()) => {},
+        Ok(()) => {
+            span.in_scope(|| info!(duration_ms, outcome = "ok", "command finished"));
+        },
         Err(why) => {
-            msg.channel_id.send_message(ctx, |m| {
-                m.content(format!("Command `{}` failed: `{:?}`", command_name, why))
-            }).await.unwrap();
+            // the unredacted error only goes to the log
+            span.in_scope(|| error!(duration_ms, outcome = "error", "command failed: {:?}", why));
+
+            if let Err(why) = say(ctx, msg.channel_id, failure_message(command_name, &why)).await {
+                warn!("failed to report command failure: {:?}", why);
+            }
         },
     }
 }
 
 #[command]
 #[checks(InProject)]
+#[sub_commands(login_cancel)]
 async fn login(ctx: &Context, msg: &Message) -> CommandResult {
-    let lock = ctx.data.read().await;
-    let scrapper = lock.get::<Scrapper>().unwrap().clone();
-    let mut scrapper = scrapper.write().await;
+    let span = command_span(ctx, msg).await;
+    login_flow(ctx, msg.channel_id, &msg.author).instrument(span).await
+}
+
+/// Abandons a login stuck waiting for a Steam Guard code, captcha or QR approval.
+#[command("cancel")]
+#[checks(Owner)]
+async fn login_cancel(ctx: &Context, msg: &Message) -> CommandResult {
+    let span = command_span(ctx, msg).await;
+    async {
+        let scrapper = {
+            let lock = ctx.data.read().await;
+            lock.get::<ScrapperHandle>().unwrap().clone()
+        };
+
+        if !scrapper.login_in_progress() {
+            say(ctx, msg.channel_id, "No login in progress").await?;
+            return Ok(());
+        }
 
-    msg.channel_id.say(&ctx.http, "Logging in...").await?;
+        scrapper.cancel_login().await.map_err(command_error)?;
+        say(ctx, msg.channel_id, "Login cancelled").await?;
+
+        Ok::<_, CommandError>(())
+    }.instrument(span).await
+}
+
+async fn login_flow(ctx: &Context, channel_id: ChannelId, user: &User) -> CommandResult {
+    let why = match run_login(ctx, channel_id, user).await {
+        Ok(()) => return Ok(()),
+        Err(why) => why,
+    };
+
+    match why.downcast_ref::<FailedPage>() {
+        // the page can show account details, so it only goes to the owner
+        Some(failed) => {
+            let owner_id = ctx.data.read().await.get::<Config>().unwrap().owner_id;
+            let dm = UserId(owner_id).create_dm_channel(&ctx).await?;
+            send_failed_page(ctx, dm.id, &failed.reason, &failed.capture).await?;
+            if dm.id != channel_id {
+                say(ctx, channel_id, format!("Login failed: {}. The page it failed on was sent to the owner.", failed.reason)).await?;
+            }
+            Ok(())
+        },
+        None => Err(command_error(why)),
+    }
+}
+
+async fn run_login(ctx: &Context, channel_id: ChannelId, user: &User) -> Result<()> {
+    let (scrapper, cfg) = {
+        let lock = ctx.data.read().await;
+        (lock.get::<ScrapperHandle>().unwrap().clone(), lock.get::<Config>().unwrap().clone())
+    };
+    let attempts = cfg.steam_guard_attempts;
+
+    say(ctx, channel_id, "Logging in...").await?;
 
     // scrapper.logout()?;
-    let res = scrapper.login().await?;
-    if let LoginResult::AuthCodeNeeded = res {
-        msg.channel_id.say(&ctx.http, "Enter Steam Guard auth code:").await?;
-        if let Some(answer) = &msg.author.await_reply(&ctx).timeout(Duration::from_secs(120)).await {
-            scrapper.provide_auth_code(answer.content.clone())?;
-        } else {
-            return Err(CommandError::from(anyhow!("No auth code provided")));
+    let mut res = scrapper.login(&user.tag()).await?;
+
+    // Steam may answer a captcha with another one before moving on, a wrong answer also gets a
+    // new captcha
+    let mut captchas = 0;
+    while let LoginResult::Captcha(image) = &res {
+        if captchas >= attempts {
+            return abort_login(ctx, &scrapper, channel_id, Some(anyhow!("Captcha answered wrong {} times", attempts))).await;
+        }
+        captchas += 1;
+
+        let answer = match login_dm(ctx, channel_id, user, "Captcha needed, check your DMs").await {
+            Ok(dm_id) => prompt_captcha(ctx, dm_id, user, image).await,
+            Err(why) => Err(why),
+        };
+
+        res = match answer {
+            Ok(Some(answer)) => scrapper.provide_captcha(answer).await?,
+            Ok(None) => return abort_login(ctx, &scrapper, channel_id, None).await,
+            Err(why) => return abort_login(ctx, &scrapper, channel_id, Some(why)).await,
+        };
+    }
+
+    if let Some(reason) = res.failure_reason() {
+        return Err(match res.capture() {
+            Some(capture) => FailedPage { reason: reason.to_string(), capture: capture.clone() }.into(),
+            None => anyhow!(reason),
+        });
+    }
+
+    if let LoginResult::QrApprovalNeeded(challenge) = res {
+        say(ctx, channel_id, "QR code sent to the owner, approve the login in the Steam mobile app").await?;
+        let timeout = Duration::from_secs(cfg.qr_login_timeout_secs);
+        if let Err(why) = await_qr_approval(ctx, &scrapper, challenge, UserId(cfg.owner_id), timeout).await {
+            return abort_login(ctx, &scrapper, channel_id, Some(why)).await;
+        }
+    } else if let LoginResult::AuthCodeNeeded = res {
+        let res = match login_dm(ctx, channel_id, user, "Steam Guard code needed, check your DMs").await {
+            Ok(dm_id) => prompt_auth_code(ctx, &scrapper, dm_id, user, attempts).await,
+            Err(why) => Err(why),
+        };
+
+        match res {
+            Ok(true) => {},
+            Ok(false) => return abort_login(ctx, &scrapper, channel_id, None).await,
+            Err(why) => return abort_login(ctx, &scrapper, channel_id, Some(why)).await,
+        }
+    }
+
+    say(ctx, channel_id, "Login successful").await?;
+
+    Ok(())
+}
+
+/// Gives up on a login waiting for user input, either because they replied `cancel` or because
+/// asking them failed.
+async fn abort_login(ctx: &Context, scrapper: &ScrapperHandle, channel_id: ChannelId, why: Option<anyhow::Error>) -> Result<()> {
+    scrapper.cancel_login().await?;
+    match why {
+        Some(why) => Err(why),
+        None => {
+            say(ctx, channel_id, "Login cancelled").await?;
+            Ok(())
+        },
+    }
+}
+
+/// The user's DM channel for login prompts, pointing them there if the login was started in
+/// another channel.
+async fn login_dm(ctx: &Context, channel_id: ChannelId, user: &User, note: &str) -> Result<ChannelId> {
+    let dm = user.create_dm_channel(&ctx).await.map_err(|why| anyhow!("could not open DM: {}", why))?;
+    if dm.id != channel_id {
+        say(ctx, channel_id, note).await?;
+    }
+    Ok(dm.id)
+}
+
+/// Posts why a login failed together with a screenshot and the text of the page it failed on.
+async fn send_failed_page(ctx: &Context, channel_id: ChannelId, reason: &str, capture: &PageCapture) -> Result<()> {
+    let text: String = capture.text.replace("```", "'''").chars().take(1500).collect();
+    let text = if text.trim().is_empty() { "(no text)".to_string() } else { text };
+    let content = format!("Login failed: {}\n```{}```", reason, text);
+    post(ctx, channel_id, content, Some((capture.screenshot.clone(), "login.png".to_string()))).await?;
+
+    Ok(())
+}
+
+/// Relays the captcha image in DMs and returns the typed answer, or `None` if the user replied
+/// `cancel`.
+async fn prompt_captcha(ctx: &Context, dm_id: ChannelId, user: &User, image: &[u8]) -> Result<Option<String>> {
+    let content = "Steam wants a captcha solved, type the characters from the image (or `cancel`):";
+    post(ctx, dm_id, content, Some((image.to_vec(), "captcha.png".to_string()))).await?;
+
+    let answer = user.await_reply(&ctx)
+        .channel_id(dm_id)
+        .timeout(Duration::from_secs(120))
+        .await
+        .ok_or_else(|| anyhow!("No captcha answer provided"))?;
+
+    if answer.content.trim().eq_ignore_ascii_case("cancel") {
+        return Ok(None);
+    }
+    Ok(Some(answer.content.trim().to_string()))
+}
+
+/// Asks for the Steam Guard code in DMs, re-prompting on a wrong code. Returns `false` if the
+/// user replied `cancel`.
+async fn prompt_auth_code(ctx: &Context, scrapper: &ScrapperHandle, dm_id: ChannelId, user: &User, attempts: u32) -> Result<bool> {
+    say(ctx, dm_id, "Enter Steam Guard auth code (or `cancel`):").await?;
+
+    for attempt in 1..=attempts {
+        let answer = user.await_reply(&ctx)
+            .channel_id(dm_id)
+            .timeout(Duration::from_secs(120))
+            .await
+            .ok_or_else(|| anyhow!("No auth code provided"))?;
+
+        if answer.content.trim().eq_ignore_ascii_case("cancel") {
+            return Ok(false);
+        }
+
+        if scrapper.provide_auth_code(answer.content.clone()).await? == AuthCodeResult::Success {
+            return Ok(true);
+        }
+
+        if attempt < attempts {
+            say(ctx, dm_id, format!("Wrong code, try again ({}/{}):", attempt, attempts)).await?;
         }
     }
 
-    msg.channel_id.say(&ctx.http, "Login successful").await?;
+    Err(anyhow!("Wrong Steam Guard code entered {} times", attempts))
+}
 
+async fn send_qr_code(ctx: &Context, dm_id: ChannelId, challenge: &QrChallenge) -> Result<()> {
+    let png = challenge.render_png()?;
+    post(ctx, dm_id, "Scan this with the Steam mobile app to log in:", Some((png, "login.png".to_string()))).await?;
     Ok(())
 }
 
+/// DMs the QR challenge to the owner and polls until the login is approved, re-sending the code
+/// whenever Steam rotates it.
+async fn await_qr_approval(ctx: &Context, scrapper: &ScrapperHandle, mut challenge: QrChallenge, owner_id: UserId, timeout: Duration) -> Result<()> {
+    let dm = owner_id.create_dm_channel(&ctx).await?;
+    send_qr_code(ctx, dm.id, &challenge).await?;
+
+    let deadline = time::Instant::now() + timeout;
+    loop {
+        time::sleep(challenge.interval).await;
+
+        let challenge_url = challenge.challenge_url.clone();
+        if scrapper.poll_qr_login(&mut challenge).await? {
+            return Ok(());
+        }
+
+        if time::Instant::now() >= deadline {
+            return Err(anyhow!("QR login wasn't approved in time"));
+        }
+
+        if challenge.challenge_url != challenge_url {
+            send_qr_code(ctx, dm.id, &challenge).await?;
+        }
+    }
+}
+
 #[command]
 #[checks(InProject)]
 async fn logout(ctx: &Context, msg: &Message) -> CommandResult {
-    let lock = ctx.data.read().await;
-    let scrapper = lock.get::<Scrapper>().unwrap().clone();
-    let mut scrapper = scrapper.write().await;
+    let span = command_span(ctx, msg).await;
+    async {
+        let scrapper = {
+            let lock = ctx.data.read().await;
+            lock.get::<ScrapperHandle>().unwrap().clone()
+        };
 
-    msg.channel_id.say(&ctx.http, "Logging out...").await?;
+        say(ctx, msg.channel_id, "Logging out...").await?;
 
-    scrapper.logout()?;
+        scrapper.logout().await.map_err(command_error)?;
 
-    msg.channel_id.say(&ctx.http, "Logout successful").await?;
+        say(ctx, msg.channel_id, "Logout successful").await?;
 
-    Ok(())
+        Ok::<_, CommandError>(())
+    }.instrument(span).await
 }
 
 
 #[command]
 #[checks(InProject)]
-async fn stats(ctx: &Context, msg: &Message) -> CommandResult {
-    let scrapper = {
+async fn stats(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let span = command_span(ctx, msg).await;
+    async {
+        let (scrapper, ttl_secs) = {
+            let lock = ctx.data.read().await;
+            (lock.get::<ScrapperHandle>().unwrap().clone(), lock.get::<Config>().unwrap().stats_cache_ttl_secs)
+        };
+        let fresh = args.raw().any(|arg| arg == "--fresh");
+        let max_age = if fresh { chrono::Duration::zero() } else { chrono::Duration::seconds(ttl_secs as i64) };
+
+        let mut msg = say(ctx, msg.channel_id, "Loading...").await?;
+
+        let content = match scrapper.stats_within(max_age).await {
+            Ok((at, stats)) => format!("As of {} seconds ago\n{}", (Utc::now() - at).num_seconds(), stats.describe()),
+            // don't leave the channel empty-handed while someone is entering a Steam Guard code
+            Err(why) => match scrapper.snapshot().stats {
+                Some((at, stats)) if scrapper.login_in_progress() => format!(
+                    "Login in progress, showing stats from {}\n{}", at.format("%Y-%m-%d %H:%M:%S UTC"), stats.describe()
+                ),
+                _ => return Err(command_error(why)),
+            },
+        };
+
+        let content = redacted(ctx, &content).await;
+        msg.edit(ctx, |m| m.content(content)).await?;
+
+        Ok::<_, CommandError>(())
+    }.instrument(span).await
+}
+
+/// `screenshot [page] [css selector]`, clipping to the selected element if one is given.
+#[command]
+#[checks(InProject)]
+async fn screenshot(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let span = command_span(ctx, msg).await;
+    async {
+        let scrapper = {
+            let lock = ctx.data.read().await;
+            lock.get::<ScrapperHandle>().unwrap().clone()
+        };
+        let page = args.single::<String>().unwrap_or_else(|_| "app".to_string());
+        let clip = args.remains().map(|s| s.to_string());
+
+        say(ctx, msg.channel_id, "Taking screenshot...").await?;
+
+        let png = scrapper.screenshot(page.clone(), clip).await.map_err(command_error)?;
+        post(ctx, msg.channel_id, "", Some((png, format!("{}.png", page)))).await?;
+
+        Ok::<_, CommandError>(())
+    }.instrument(span).await
+}
+
+#[command]
+#[checks(InProject)]
+async fn players(ctx: &Context, msg: &Message) -> CommandResult {
+    let players = {
         let lock = ctx.data.read().await;
-        lock.get::<Scrapper>().unwrap().clone()
+        lock.get::<PlayerTracker>().unwrap().clone()
     };
-    let mut scrapper = scrapper.write().await;
+    let players = players.read().await;
 
-    let mut msg = msg.channel_id.say(&ctx.http, "Loading...").await?;
+    say(ctx, msg.channel_id, format!(
+        "**Today**\n```{}```**Average players by hour (UTC)**\n```{}```",
+        players.summary(Utc::now().naive_utc().date()),
+        players.heatmap(),
+    )).await?;
 
-    let stats = scrapper.get_stats().await?;
+    Ok(())
+}
 
-    msg.edit(ctx, |m| m.content(format!("```{:#?}```", stats))).await?;
+#[command]
+#[checks(InProject)]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let (status, interval_started, started_at, scrapper) = {
+        let lock = ctx.data.read().await;
+        (lock.get::<ScrapperStatus>().unwrap().clone(), lock.get::<IntervalStarted>().cloned(), *lock.get::<StartedAt>().unwrap(), lock.get::<ScrapperHandle>().unwrap().clone())
+    };
+    let status = status.lock().unwrap().clone();
+    let snapshot = scrapper.snapshot();
+
+    let last_scrape = match status.last_scrape() {
+        Some(s) => format!("{} ({})", match &s.result {
+            Ok(()) => "ok".to_string(),
+            Err(why) => format!("failed: {}", why),
+        }, s.at.format("%Y-%m-%d %H:%M:%S UTC")),
+        None => "never".to_string(),
+    };
+    let format_time = |at: Option<DateTime<Utc>>| at.map_or("unknown".to_string(), |at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string());
+    let uptime = Utc::now() - started_at;
+
+    say(ctx, msg.channel_id, format!(
+        "```Login: {} (since {})\nSession expires: {}\nLast authenticated fetch: {}\nLast scrape: {}\nInterval: {}\nUptime: {}d {}h {}m```",
+        status.state(),
+        status.since().format("%Y-%m-%d %H:%M:%S UTC"),
+        format_time(snapshot.session_expiry),
+        format_time(snapshot.last_authenticated_fetch),
+        last_scrape,
+        if interval_started.map_or(false, |x| *x) { "running" } else { "stopped" },
+        uptime.num_days(), uptime.num_hours() % 24, uptime.num_minutes() % 60,
+    )).await?;
 
     Ok(())
 }
@@ -142,46 +625,201 @@ async fn stats(ctx: &Context, msg: &Message) -> CommandResult {
 #[command]
 #[checks(InProject)]
 async fn start_interval(ctx: &Context, msg: &Message) -> CommandResult {
-    msg.channel_id.say(&ctx.http, "Starting interval...").await?;
+    say(ctx, msg.channel_id, "Starting interval...").await?;
 
     let (interval_started, config, scrapper) = {
         let lock = ctx.data.read().await;
-        (lock.get::<IntervalStarted>().cloned(), lock.get::<Config>().cloned(), lock.get::<Scrapper>().cloned())
+        (lock.get::<IntervalStarted>().cloned(), lock.get::<Config>().cloned(), lock.get::<ScrapperHandle>().cloned())
     };
     if interval_started.map_or(false, |x| *x) {
-        msg.channel_id.say(&ctx.http, "Already started!").await?;
+        say(ctx, msg.channel_id, "Already started!").await?;
         return Ok(());
     }
 
-    interval::start_interval(config.unwrap(), scrapper.unwrap(), ctx.http.clone(), ctx.data.clone());
+    let handle = interval::start_interval(config.unwrap(), scrapper.unwrap(), ctx.http.clone(), ctx.data.clone());
+    ctx.data.write().await.insert::<IntervalHandle>(handle.map(Arc::new));
+
+    say(ctx, msg.channel_id, "Interval started!").await?;
+
+    Ok(())
+}
+
+/// Settings applied without a restart. Anything else is persisted but only picked up on the
+/// next start.
+const LIVE_CONFIG_KEYS: &[&str] = &[
+    "prefix", "role_id", "owner_id", "updates_channel_id", "updates_interval_secs",
+    "watched_key_batches", "steam_guard_attempts", "qr_login_timeout_secs", "stats_cache_ttl_secs",
+    "report_screenshot_page", "session_probe_interval_secs", "session_expiry_warning_secs",
+];
+
+/// Swaps the live config. The session monitor is restarted if a setting it was started with
+/// changed. The interval is restarted only if it's running and a setting it was started with
+/// changed, it picks up where it left off.
+async fn apply_config(ctx: &Context, old: &Config, cfg: Config) {
+    let (handle, running, scrapper, monitor) = {
+        let mut lock = ctx.data.write().await;
+        lock.insert::<Redactor>(Redactor::from_config(&cfg));
+        lock.insert::<Config>(cfg.clone());
+        (
+            lock.get::<IntervalHandle>().cloned().flatten(),
+            lock.get::<IntervalStarted>().map_or(false, |started| **started),
+            lock.get::<ScrapperHandle>().unwrap().clone(),
+            lock.get::<SessionMonitorHandle>().cloned().flatten(),
+        )
+    };
+
+    if session::settings_changed(old, &cfg) {
+        if let Some(monitor) = monitor {
+            monitor.abort();
+        }
+        let monitor = session::start_session_monitor(cfg.clone(), scrapper.clone(), ctx.http.clone());
+        ctx.data.write().await.insert::<SessionMonitorHandle>(monitor.map(Arc::new));
+    }
+
+    if !running || !interval::settings_changed(old, &cfg) {
+        return;
+    }
+
+    if let Some(handle) = handle {
+        handle.abort();
+        let handle = interval::start_interval(cfg, scrapper, ctx.http.clone(), ctx.data.clone());
+
+        let mut lock = ctx.data.write().await;
+        if handle.is_none() {
+            lock.insert::<IntervalStarted>(Arc::new(false));
+        }
+        lock.insert::<IntervalHandle>(handle.map(Arc::new));
+    }
+}
+
+#[command]
+#[checks(Owner)]
+#[sub_commands(config_get, config_set, config_reload)]
+async fn config(ctx: &Context, msg: &Message) -> CommandResult {
+    say(ctx, msg.channel_id, "Usage: `config get <key>`, `config set <key> <value>`, `config reload`").await?;
+    Ok(())
+}
+
+#[command("get")]
+#[checks(Owner)]
+async fn config_get(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = args.single::<String>()?;
+    let cfg = {
+        let lock = ctx.data.read().await;
+        lock.get::<Config>().unwrap().clone()
+    };
+
+    let value = cfg.get_display(&key).ok_or_else(|| anyhow!("unknown setting `{}`", key))?;
+    say(ctx, msg.channel_id, format!("`{}` = `{}`", key, value)).await?;
+
+    Ok(())
+}
+
+#[command("set")]
+#[checks(Owner)]
+async fn config_set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = args.single::<String>()?;
+    let value = args.rest().trim().to_string();
+
+    let (source, old) = {
+        let lock = ctx.data.read().await;
+        (lock.get::<ConfigSource>().unwrap().clone(), lock.get::<Config>().unwrap().clone())
+    };
+
+    let cfg = source.set(&key, &value)?;
+    cfg.audit(&msg.author, "set", &key, old.get_display(&key), cfg.get_display(&key))?;
+    apply_config(ctx, &old, cfg).await;
+
+    let note = if LIVE_CONFIG_KEYS.contains(&key.as_str()) { "" } else { " (takes effect after a restart)" };
+    say(ctx, msg.channel_id, format!("`{}` updated{}", key, note)).await?;
+
+    Ok(())
+}
+
+#[command("reload")]
+#[checks(Owner)]
+async fn config_reload(ctx: &Context, msg: &Message) -> CommandResult {
+    let (source, old) = {
+        let lock = ctx.data.read().await;
+        (lock.get::<ConfigSource>().unwrap().clone(), lock.get::<Config>().unwrap().clone())
+    };
+
+    let cfg = source.load()?;
+    let errors = cfg.validate();
+    if !errors.is_empty() {
+        return Err(CommandError::from(anyhow!("config not reloaded:\n{}", errors.join("\n"))));
+    }
+
+    cfg.audit(&msg.author, "reload", "*", None, None)?;
+    apply_config(ctx, &old, cfg).await;
 
-    msg.channel_id.say(&ctx.http, "Interval started!").await?;
+    say(ctx, msg.channel_id, "Config reloaded").await?;
 
     Ok(())
 }
 
-impl TypeMapKey for Scrapper {
-    type Value = Arc<RwLock<Scrapper>>;
+impl TypeMapKey for ScrapperHandle {
+    type Value = ScrapperHandle;
+}
+
+impl TypeMapKey for IntervalState {
+    type Value = Arc<Mutex<IntervalState>>;
+}
+
+impl TypeMapKey for PlayerTracker {
+    type Value = Arc<RwLock<PlayerTracker>>;
 }
 
 impl TypeMapKey for Config {
     type Value = Config;
 }
 
+impl TypeMapKey for ScrapperStatus {
+    type Value = Arc<std::sync::Mutex<ScrapperStatus>>;
+}
+
 pub struct IntervalStarted;
 
 impl TypeMapKey for IntervalStarted {
     type Value = Arc<bool>;
 }
 
+impl TypeMapKey for Redactor {
+    type Value = Redactor;
+}
+
+impl TypeMapKey for ConfigSource {
+    type Value = ConfigSource;
+}
+
+pub struct IntervalHandle;
+
+impl TypeMapKey for IntervalHandle {
+    type Value = Option<Arc<JoinHandle<()>>>;
+}
+
+pub struct SessionMonitorHandle;
+
+impl TypeMapKey for SessionMonitorHandle {
+    type Value = Option<Arc<JoinHandle<()>>>;
+}
+
+pub struct StartedAt;
+
+impl TypeMapKey for StartedAt {
+    type Value = DateTime<Utc>;
+}
+
 impl TypeMapKey for Bot {
     type Value = Arc<Bot>;
 }
 
 impl Bot {
-    pub async fn new(config: Config, scrapper: Arc<RwLock<Scrapper>>) -> Self {
+    pub async fn new(config: Config, source: ConfigSource, scrapper: ScrapperHandle, players: Arc<RwLock<PlayerTracker>>) -> Self {
+        // the prefix comes from the live config so `!config set prefix` applies immediately
         let framework = StandardFramework::new()
-            .configure(|c| c.prefix(config.prefix.clone()))
+            .configure(|c| c.prefix("").dynamic_prefix(dynamic_prefix))
+            .before(before)
             .after(after)
             .group(&GENERAL_GROUP);
 
@@ -194,10 +832,19 @@ impl Bot {
             .await
             .expect("Error creating client");
 
+        let status = scrapper.status();
+
         {
             let mut lock = client.data.write().await;
-            lock.insert::<Scrapper>(scrapper);
+            lock.insert::<ScrapperHandle>(scrapper);
+            lock.insert::<ScrapperStatus>(status);
+            lock.insert::<StartedAt>(Utc::now());
+            lock.insert::<IntervalState>(Arc::new(Mutex::new(IntervalState::default())));
+            lock.insert::<CommandSpans>(Arc::new(Mutex::new(HashMap::new())));
+            lock.insert::<PlayerTracker>(players);
+            lock.insert::<Redactor>(Redactor::from_config(&config));
             lock.insert::<Config>(config.clone());
+            lock.insert::<ConfigSource>(source);
         }
 
         Self {
@@ -207,10 +854,10 @@ impl Bot {
     }
 
     pub async fn run(&mut self) -> Result<()> {
-        println!("bot started");
+        info!("bot started");
 
         if let Err(why) = self.client.start().await {
-            println!("An error occurred while running the client: {:?}", why);
+            error!("An error occurred while running the client: {:?}", why);
         }
 
         Ok(())
@@ -218,3 +865,40 @@ impl Bot {
 
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrape_error_from_a_command_gets_recovery_advice() {
+        let why = command_error(anyhow::Error::from(ScrapeError::SessionExpired));
+
+        assert_eq!(
+            failure_message("stats", &why),
+            "Command `stats` failed: the Steam session expired. Log in again with `login`.",
+        );
+    }
+
+    #[test]
+    fn scrape_error_under_context_is_found() {
+        let why = command_error(anyhow::Error::from(ScrapeError::RateLimited).context("fetching stats"));
+
+        assert_eq!(find_scrape_error(&why), Some(&ScrapeError::RateLimited));
+    }
+
+    #[test]
+    fn boxed_scrape_error_is_found() {
+        let why: CommandError = Box::new(ScrapeError::NotLoggedIn);
+
+        assert_eq!(find_scrape_error(&why), Some(&ScrapeError::NotLoggedIn));
+    }
+
+    #[test]
+    fn other_errors_are_shown_as_is() {
+        let why = command_error(anyhow!("unknown setting `foo`"));
+
+        assert_eq!(find_scrape_error(&why), None);
+        assert!(failure_message("config", &why).starts_with("Command `config` failed: `unknown setting `foo`"));
+    }
+}
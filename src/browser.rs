@@ -0,0 +1,478 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use headless_chrome::{Browser, LaunchOptions, Tab};
+use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam, DeleteCookies, GetAllCookies};
+use headless_chrome::protocol::cdp::Page::{CaptureScreenshotFormatOption, Viewport};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, warn};
+
+use crate::Config;
+use crate::scrape_error::ScrapeError;
+
+/// How to get hold of a browser: attach to a running one over its DevTools websocket, or launch
+/// a local one.
+#[derive(Clone)]
+pub struct BrowserSettings {
+    ws_url: Option<String>,
+    path: Option<PathBuf>,
+    user_data_dir: Option<PathBuf>,
+    /// default wait for page elements
+    element_timeout: Duration,
+    /// how long the login form may take to show an outcome after submitting
+    login_timeout: Duration,
+    auth_code_timeout: Duration,
+}
+
+impl BrowserSettings {
+    pub fn from_config(cfg: &Config) -> Self {
+        let non_empty = |s: &str| if s.is_empty() { None } else { Some(s.to_string()) };
+
+        BrowserSettings {
+            ws_url: non_empty(&cfg.chrome_ws_url),
+            path: non_empty(&cfg.chrome_path).map(PathBuf::from),
+            user_data_dir: non_empty(&cfg.chrome_user_data_dir).map(PathBuf::from),
+            element_timeout: Duration::from_secs(cfg.browser_timeout_secs),
+            login_timeout: Duration::from_secs(cfg.login_timeout_secs),
+            auth_code_timeout: Duration::from_secs(cfg.auth_code_timeout_secs),
+        }
+    }
+}
+
+/// Charts on the partner pages are drawn by script after the page loads, as SVG or canvas.
+const CHART_SELECTOR: &str = "svg, canvas";
+
+/// Size of the whole document rather than the window, as `width,height`.
+const PAGE_SIZE_JS: &str = "[document.documentElement.scrollWidth, document.documentElement.scrollHeight].join(',')";
+
+/// Tells apart the states the login form can end up in after submitting. Returns one of
+/// `success`, `auth_code`, `captcha`, `rate_limited`, `wrong_password`, `error:<message>`, or an
+/// empty string while the page is still loading.
+const LOGIN_OUTCOME_JS: &str = r#"(function() {
+    const visible = sel => { const el = document.querySelector(sel); return el !== null && el.offsetParent !== null; };
+    if (!location.pathname.startsWith('/login')) return 'success';
+    if (visible('input#authcode')) return 'auth_code';
+    if (visible('#captcha_entry')) return 'captcha';
+    const error = document.querySelector('#error_display');
+    const message = error !== null && error.offsetParent !== null ? error.innerText.trim() : '';
+    if (message === '') return '';
+    if (/too many/i.test(message)) return 'rate_limited';
+    if (/incorrect/i.test(message)) return 'wrong_password';
+    return 'error:' + message;
+})()"#;
+
+/// What the login URL showed: the form, or a redirect away from it because the saved cookies
+/// are still logged in.
+const LOGIN_FORM_JS: &str = r#"(function() {
+    if (location.protocol === 'about:') return '';
+    if (!location.pathname.startsWith('/login')) return 'logged_in';
+    if (document.querySelector('input#username') !== null) return 'form';
+    return '';
+})()"#;
+
+/// Screenshot and visible text of the page, kept when a browser login goes wrong.
+#[derive(Clone, PartialEq)]
+pub struct PageCapture {
+    pub screenshot: Vec<u8>,
+    pub text: String,
+}
+
+/// A browser login error together with what the page looked like at the time.
+pub struct FailedPage {
+    pub reason: String,
+    pub capture: PageCapture,
+}
+
+impl fmt::Display for FailedPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl fmt::Debug for FailedPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for FailedPage {}
+
+/// Where the login form left us.
+pub enum LoginPage {
+    /// There was no login form, the loaded cookies are still good. Carries the browser's cookies,
+    /// which may have been refreshed on the way.
+    AlreadyLoggedIn(Vec<Cookie>),
+    AuthCodeNeeded,
+    /// PNG of the captcha image.
+    Captcha(Vec<u8>),
+    WrongPassword(PageCapture),
+    RateLimited(PageCapture),
+    LoggedIn(Vec<Cookie>),
+}
+
+pub enum AuthCodePage {
+    Accepted(Vec<Cookie>),
+    /// The form was reset for another attempt.
+    WrongCode,
+}
+
+enum Command {
+    Login {
+        url: String,
+        username: String,
+        password: String,
+        cookies: Vec<CookieParam>,
+        reply: oneshot::Sender<Result<LoginPage>>,
+    },
+    SubmitAuthCode {
+        auth_code: String,
+        reply: oneshot::Sender<Result<AuthCodePage>>,
+    },
+    SubmitCaptcha {
+        answer: String,
+        password: String,
+        reply: oneshot::Sender<Result<LoginPage>>,
+    },
+    Screenshot {
+        url: String,
+        cookies: Vec<CookieParam>,
+        clip: Option<String>,
+        reply: oneshot::Sender<Result<Vec<u8>>>,
+    },
+    Logout(oneshot::Sender<Result<()>>),
+    Close(oneshot::Sender<Result<()>>),
+}
+
+/// Handle to the thread that owns the browser. `headless_chrome` blocks on every call, so all
+/// browser automation runs there instead of on the async runtime.
+#[derive(Clone)]
+pub struct BrowserWorker {
+    tx: mpsc::UnboundedSender<Command>,
+}
+
+impl BrowserWorker {
+    pub fn spawn(settings: BrowserSettings) -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let worker = Worker { settings, browser: None, tab: None };
+        thread::Builder::new()
+            .name("browser".to_string())
+            .spawn(move || worker.run(rx))?;
+
+        Ok(BrowserWorker { tx })
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<Result<T>>) -> Command) -> Result<T> {
+        let (tx, rx) = oneshot::channel();
+        let stopped = || ScrapeError::BrowserUnavailable("browser thread stopped".to_string());
+        self.tx.send(command(tx)).map_err(|_| stopped())?;
+        rx.await.map_err(|_| stopped())?
+    }
+
+    /// Opens the login page with the given cookies and submits the credentials.
+    pub async fn login(&self, url: &str, username: &str, password: &str, cookies: Vec<CookieParam>) -> Result<LoginPage> {
+        self.request(|reply| Command::Login {
+            url: url.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            cookies,
+            reply,
+        }).await
+    }
+
+    pub async fn submit_auth_code(&self, auth_code: &str) -> Result<AuthCodePage> {
+        self.request(|reply| Command::SubmitAuthCode { auth_code: auth_code.trim().to_string(), reply }).await
+    }
+
+    /// Answers the captcha on the login form. The password has to be typed again as Steam clears
+    /// it along with showing the captcha.
+    pub async fn submit_captcha(&self, answer: &str, password: &str) -> Result<LoginPage> {
+        self.request(|reply| Command::SubmitCaptcha { answer: answer.trim().to_string(), password: password.to_string(), reply }).await
+    }
+
+    /// Opens `url` with the given cookies and takes a PNG of the whole page, or only of the
+    /// element matching `clip`.
+    pub async fn screenshot(&self, url: &str, cookies: Vec<CookieParam>, clip: Option<String>) -> Result<Vec<u8>> {
+        self.request(|reply| Command::Screenshot { url: url.to_string(), cookies, clip, reply }).await
+    }
+
+    /// Deletes the browser's cookies and closes it.
+    pub async fn logout(&self) -> Result<()> {
+        self.request(Command::Logout).await
+    }
+
+    pub async fn close(&self) -> Result<()> {
+        self.request(Command::Close).await
+    }
+}
+
+struct Worker {
+    settings: BrowserSettings,
+    browser: Option<Browser>,
+    tab: Option<Arc<Tab>>,
+}
+
+impl Worker {
+    fn run(mut self, mut rx: mpsc::UnboundedReceiver<Command>) {
+        // the requester may have given up waiting, so failed replies are ignored
+        while let Some(command) = rx.blocking_recv() {
+            match command {
+                Command::Login { url, username, password, cookies, reply } => {
+                    let res = self.login(&url, &username, &password, cookies);
+                    let _ = reply.send(res.map_err(|why| self.with_capture(why)));
+                },
+                Command::SubmitAuthCode { auth_code, reply } => {
+                    let res = self.submit_auth_code(&auth_code);
+                    let _ = reply.send(res.map_err(|why| self.with_capture(why)));
+                },
+                Command::SubmitCaptcha { answer, password, reply } => {
+                    let res = self.submit_captcha(&answer, &password);
+                    let _ = reply.send(res.map_err(|why| self.with_capture(why)));
+                },
+                Command::Screenshot { url, cookies, clip, reply } => {
+                    let _ = reply.send(self.screenshot(&url, cookies, clip.as_deref()));
+                },
+                Command::Logout(reply) => {
+                    let _ = reply.send(self.logout());
+                },
+                Command::Close(reply) => {
+                    let _ = reply.send(self.close());
+                },
+            }
+        }
+
+        if let Err(why) = self.close() {
+            warn!("failed to close browser: {:?}", why);
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        match &self.browser {
+            Some(b) => b.is_open(),
+            None => false
+        }
+    }
+
+    fn open(&mut self) -> Result<Arc<Tab>> {
+        let browser = match &self.settings.ws_url {
+            Some(ws_url) => Browser::connect(ws_url.clone())
+                .map_err(|why| ScrapeError::BrowserUnavailable(format!("could not connect to Chrome at {}: {}", ws_url, why)))?,
+            None => {
+                let options = LaunchOptions::default_builder()
+                    .headless(true)
+                    .path(self.settings.path.clone())
+                    .user_data_dir(self.settings.user_data_dir.clone())
+                    .build()
+                    .map_err(|why| anyhow!("invalid browser options: {}", why))?;
+
+                Browser::new(options).map_err(|why| ScrapeError::BrowserUnavailable(format!("could not launch Chrome: {}", why)))?
+            },
+        };
+
+        let tab = browser.new_tab()?;
+        tab.set_default_timeout(self.settings.element_timeout);
+
+        self.browser = Some(browser);
+        self.tab = Some(tab.clone());
+
+        Ok(tab)
+    }
+
+    fn tab(&self) -> Result<Arc<Tab>> {
+        self.tab.clone().ok_or_else(|| anyhow!("browser is not open"))
+    }
+
+    fn capture(&self) -> Result<PageCapture> {
+        let tab = self.tab()?;
+        let screenshot = tab.capture_screenshot(CaptureScreenshotFormatOption::Png, None, None, true)?;
+        let text = tab.evaluate("document.body.innerText", false)?
+            .value
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+
+        Ok(PageCapture { screenshot, text })
+    }
+
+    /// Attaches a capture of the current page to a login error, if the browser is still there to
+    /// take one.
+    fn with_capture(&self, why: anyhow::Error) -> anyhow::Error {
+        match self.capture() {
+            Ok(capture) => FailedPage { reason: format!("{:#}", why), capture }.into(),
+            Err(_) => why,
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(tab) = self.tab.take() {
+            tab.close(true)?;
+        }
+        self.browser = None;
+        Ok(())
+    }
+
+    fn login(&mut self, url: &str, username: &str, password: &str, cookies: Vec<CookieParam>) -> Result<LoginPage> {
+        let tab = if self.is_open() { self.tab()? } else { self.open()? };
+
+        if let Err(why) = tab.set_cookies(cookies) {
+            warn!("load cookies failed: {}", why);
+        }
+
+        // a reused tab would otherwise be polled while it still shows the previous page
+        tab.navigate_to(url)?.wait_until_navigated()?;
+
+        if self.wait_for_page(&tab, LOGIN_FORM_JS)? == "logged_in" {
+            info!("already logged in");
+            return Ok(LoginPage::AlreadyLoggedIn(all_cookies(&tab)?));
+        }
+
+        tab.wait_for_element("input#username")?.click()?;
+        tab.type_str(username)?;
+
+        tab.wait_for_element("input#password")?.click()?;
+        tab.type_str(password)?.press_key("Enter")?;
+
+        self.login_outcome(&tab)
+    }
+
+    fn submit_captcha(&mut self, answer: &str, password: &str) -> Result<LoginPage> {
+        let tab = self.tab()?;
+
+        tab.evaluate("document.querySelector('#input_captcha').value = ''; document.querySelector('input#password').value = ''", false)?;
+
+        tab.wait_for_element("input#password")?.click()?;
+        tab.type_str(password)?;
+
+        tab.wait_for_element("#input_captcha")?.click()?;
+        tab.type_str(answer)?.press_key("Enter")?;
+
+        self.login_outcome(&tab)
+    }
+
+    fn login_outcome(&self, tab: &Tab) -> Result<LoginPage> {
+        Ok(match self.wait_for_page(tab, LOGIN_OUTCOME_JS)?.as_str() {
+            "auth_code" => LoginPage::AuthCodeNeeded,
+            "captcha" => LoginPage::Captcha(tab.wait_for_element("#captchaImg")?.capture_screenshot(CaptureScreenshotFormatOption::Png)?),
+            "wrong_password" => LoginPage::WrongPassword(self.capture()?),
+            "rate_limited" => LoginPage::RateLimited(self.capture()?),
+            _ => {
+                tab.wait_until_navigated()?;
+                LoginPage::LoggedIn(all_cookies(tab)?)
+            },
+        })
+    }
+
+    /// Polls the page with `js` until it reports something instead of sleeping for a fixed time.
+    fn wait_for_page(&self, tab: &Tab, js: &str) -> Result<String> {
+        let deadline = Instant::now() + self.settings.login_timeout;
+
+        loop {
+            // evaluating fails while the page navigates, that just means no outcome yet
+            let outcome = tab.evaluate(js, false).ok()
+                .and_then(|res| res.value)
+                .and_then(|v| v.as_str().map(|s| s.to_string()))
+                .unwrap_or_default();
+
+            if let Some(message) = outcome.strip_prefix("error:") {
+                return Err(anyhow!("Steam login failed: {}", message));
+            }
+            if !outcome.is_empty() {
+                return Ok(outcome);
+            }
+
+            if Instant::now() >= deadline {
+                return Err(anyhow!("login page showed no result within {:?}", self.settings.login_timeout));
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    fn submit_auth_code(&mut self, auth_code: &str) -> Result<AuthCodePage> {
+        let tab = self.tab()?;
+
+        tab.evaluate("document.querySelector('input#authcode').value = ''; document.querySelector('input#friendlyname').value = ''", false)?;
+
+        let auth_el = tab.wait_for_element("input#authcode")?;
+
+        auth_el.click()?;
+
+        tab.type_str(auth_code)?;
+
+        tab.wait_for_element("input#friendlyname")?.click()?;
+        tab.type_str("Decorporation bot")?;
+
+
+        tab.wait_for_element("#auth_buttonset_entercode > div.auth_button.leftbtn")?.click()?;
+        debug!("clicked submit btn");
+
+        match tab.wait_for_element_with_custom_timeout("#success_continue_btn", self.settings.auth_code_timeout) {
+            Ok(el) => el.click()?,
+            Err(why) => {
+                let wrong_code = tab.evaluate("document.querySelector('#auth_buttonset_incorrectcode').style.display !== 'none'", false)?
+                    .value
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+
+                if !wrong_code {
+                    return Err(why.into());
+                }
+
+                tab.wait_for_element("#auth_buttonset_incorrectcode > div.auth_button.leftbtn")?.click()?;
+                return Ok(AuthCodePage::WrongCode);
+            }
+        };
+
+        tab.wait_until_navigated()?;
+        Ok(AuthCodePage::Accepted(all_cookies(&tab)?))
+    }
+
+    fn screenshot(&mut self, url: &str, cookies: Vec<CookieParam>, clip: Option<&str>) -> Result<Vec<u8>> {
+        let tab = if self.is_open() { self.tab()? } else { self.open()? };
+
+        tab.set_cookies(cookies)?;
+        tab.navigate_to(url)?.wait_until_navigated()?;
+
+        if let Some(selector) = clip {
+            return Ok(tab.wait_for_element(selector)?.capture_screenshot(CaptureScreenshotFormatOption::Png)?);
+        }
+
+        // pages without data have no chart, they are still worth a screenshot
+        if let Err(why) = tab.wait_for_element(CHART_SELECTOR) {
+            warn!("no chart showed up on {}, taking the screenshot anyway: {}", url, why);
+        }
+        // without a clip only the window is captured, and the charts sit below it
+        Ok(tab.capture_screenshot(CaptureScreenshotFormatOption::Png, None, Some(full_page(&tab)?), true)?)
+    }
+
+    fn logout(&mut self) -> Result<()> {
+        if let Some(tab) = self.tab.clone() {
+            if let Ok(cookies) = tab.get_cookies() {
+                tab.delete_cookies(cookies.iter().map(|c| DeleteCookies {
+                    name: c.name.clone(),
+                    domain: Some(c.domain.clone()),
+                    path: None,
+                    url: None
+                }).collect())?;
+            }
+        }
+
+        self.close()
+    }
+}
+
+fn full_page(tab: &Tab) -> Result<Viewport> {
+    let size = tab.evaluate(PAGE_SIZE_JS, false)?
+        .value
+        .and_then(|v| v.as_str().map(str::to_string))
+        .ok_or_else(|| anyhow!("could not read the page size"))?;
+    let (width, height) = size.split_once(',').ok_or_else(|| anyhow!("unexpected page size {}", size))?;
+
+    Ok(Viewport { x: 0.0, y: 0.0, width: width.parse()?, height: height.parse()?, scale: 1.0 })
+}
+
+// getAllCookies rather than getCookies, so steamcommunity.com / store / login cookies are kept too
+fn all_cookies(tab: &Tab) -> Result<Vec<Cookie>> {
+    Ok(tab.call_method(GetAllCookies(None))?.cookies)
+}
@@ -0,0 +1,245 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use qrcode::QrCode;
+use qrcode::render::unicode::Dense1x2;
+use serenity::http::Http;
+use text_io::read;
+use tokio::time;
+
+use crate::Config;
+use crate::cookie_store::create_key_file;
+use crate::scrapper::{AuthCodeResult, LoginResult, Scrapper};
+
+pub struct CliArgs {
+    pub command: Option<String>,
+    pub json: bool,
+    pub check_config: bool,
+    pub config_path: String,
+    /// `--some-setting value` flags, overriding the config file and environment
+    pub overrides: Vec<(String, String)>,
+}
+
+impl CliArgs {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut res = CliArgs {
+            command: None,
+            json: false,
+            check_config: false,
+            config_path: "config.toml".to_string(),
+            overrides: vec![],
+        };
+
+        while let Some(arg) = args.next() {
+            let flag = match arg.strip_prefix("--") {
+                Some(flag) => flag.to_string(),
+                None if res.command.is_none() => {
+                    res.command = Some(arg);
+                    continue;
+                },
+                None => return Err(anyhow!("unexpected argument {}", arg)),
+            };
+
+            match flag.as_str() {
+                "json" => res.json = true,
+                "check-config" => res.check_config = true,
+                _ => {
+                    let (key, value) = match flag.split_once('=') {
+                        Some((key, value)) => (key.to_string(), value.to_string()),
+                        None => (flag.clone(), args.next().ok_or_else(|| anyhow!("--{} needs a value", flag))?),
+                    };
+
+                    if key == "config" {
+                        res.config_path = value;
+                    } else {
+                        res.overrides.push((key.replace('-', "_"), value));
+                    }
+                },
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+fn prompt(label: &str, default: &str) -> String {
+    if default.is_empty() {
+        print!("{}: ", label);
+    } else {
+        print!("{} [{}]: ", label, default);
+    }
+    io::stdout().flush().unwrap();
+
+    let answer: String = read!("{}\n");
+    let answer = answer.trim();
+    if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer.to_string()
+    }
+}
+
+/// Walks through creating `config.toml`, checking the bot token and channel ids against Discord.
+pub async fn setup(path: &str) -> Result<()> {
+    let mut answers: Vec<(String, String)> = vec![];
+    let mut set = |key: &str, value: String| answers.push((key.to_uppercase(), value));
+
+    let http = loop {
+        let token = prompt("Discord bot token", "");
+        let http = Http::new_with_token(&token);
+        match http.get_current_user().await {
+            Ok(user) => {
+                println!("Token belongs to {}", user.tag());
+                set("bot_token", token);
+                break http;
+            },
+            Err(why) => println!("Invalid bot token: {}", why),
+        }
+    };
+
+    set("prefix", prompt("Command prefix", "!"));
+    set("owner_id", prompt("Owner user id", ""));
+    set("role_id", prompt("Project role id", ""));
+
+    for key in ["updates_channel_id", "reviews_channel_id"] {
+        let id = loop {
+            let id = prompt(&format!("{} (0 to disable)", key), "0");
+            match id.parse::<u64>() {
+                Ok(0) => break id,
+                Ok(parsed) => match http.get_channel(parsed).await {
+                    Ok(_) => break id,
+                    Err(why) => println!("Channel {} is not visible to the bot: {}", parsed, why),
+                },
+                Err(_) => println!("{} is not a valid id", id),
+            }
+        };
+        set(key, id);
+    }
+    set("updates_interval_secs", prompt("Update interval in seconds", "300"));
+
+    let login_mode = prompt("Login mode (password/qr)", "password");
+    set("steam_login", prompt("Steam login", ""));
+    if login_mode != "qr" {
+        set("steam_password", prompt("Steam password", ""));
+    }
+    set("login_mode", login_mode);
+    set("stats_url", prompt("Partner stats page URL", ""));
+    set("app_id", prompt("Steam app id", "0"));
+    set("webhook_url", prompt("Webhook URL", ""));
+    set("data_dir", prompt("Data directory", "."));
+    set("cookies_path", prompt("Cookies file", "cookies.json"));
+    let key_path = prompt("Cookies encryption key file", "cookies.key");
+    write_cookies_key(&key_path)?;
+    set("cookies_key_path", key_path);
+
+    // everything was collected as strings, go through envy like the environment does to get
+    // proper types and defaults for the rest
+    let cfg: Config = envy::from_iter(answers)?;
+
+    fs::write(path, toml::to_string(&cfg)?)?;
+    println!("Saved {}", path);
+
+    Ok(())
+}
+
+fn write_cookies_key(path: &str) -> Result<()> {
+    if create_key_file(Path::new(path))? {
+        println!("Saved the cookies encryption key to {}", path);
+    } else {
+        println!("Keeping the existing key in {}", path);
+    }
+
+    Ok(())
+}
+
+/// Validates the config, including the Discord token and channels, and prints every problem found.
+pub async fn check_config(cfg: &Config) -> Result<()> {
+    let mut errors = cfg.validate();
+    if errors.is_empty() {
+        errors = cfg.validate_discord().await;
+    }
+
+    if errors.is_empty() {
+        println!("Config OK");
+        if cfg.updates_channel_id == 0 {
+            println!("note: updates are disabled because `updates_channel_id` is 0");
+        }
+        return Ok(());
+    }
+
+    for error in &errors {
+        println!("error: {}", error);
+    }
+    Err(anyhow!("{} config error(s)", errors.len()))
+}
+
+/// Logs into Steam from the terminal and saves the cookies.
+pub async fn login(cfg: Config) -> Result<()> {
+    let mut scrapper = Scrapper::new(cfg.clone())?;
+
+    match scrapper.login("cli").await? {
+        LoginResult::Success => {},
+        LoginResult::AuthCodeNeeded => {
+            let mut attempt = 1;
+            loop {
+                let code = prompt("Steam Guard code", "");
+                if scrapper.provide_auth_code(code).await? == AuthCodeResult::Success {
+                    break;
+                }
+                if attempt >= cfg.steam_guard_attempts {
+                    scrapper.cancel_login().await?;
+                    return Err(anyhow!("Wrong Steam Guard code entered {} times", attempt));
+                }
+                println!("Wrong code, try again");
+                attempt += 1;
+            }
+        },
+        LoginResult::QrApprovalNeeded(mut challenge) => {
+            let deadline = time::Instant::now() + Duration::from_secs(cfg.qr_login_timeout_secs);
+            let mut shown = String::new();
+            loop {
+                if shown != challenge.challenge_url {
+                    let code = QrCode::new(challenge.challenge_url.as_bytes())?;
+                    println!("Scan with the Steam mobile app:\n{}", code.render::<Dense1x2>().build());
+                    shown = challenge.challenge_url.clone();
+                }
+
+                time::sleep(challenge.interval).await;
+                if scrapper.poll_qr_login(&mut challenge).await? {
+                    break;
+                }
+
+                if time::Instant::now() >= deadline {
+                    scrapper.cancel_login().await?;
+                    return Err(anyhow!("QR login wasn't approved in time"));
+                }
+            }
+        },
+        other => return Err(anyhow!("login failed: {}", other.failure_reason().unwrap_or_default())),
+    }
+
+    println!("Login successful");
+    Ok(())
+}
+
+/// Prints one stats snapshot and exits. Needs a saved session, run `login` first.
+pub async fn scrape(cfg: Config, json: bool) -> Result<()> {
+    let mut scrapper = Scrapper::new(cfg)?;
+
+    if scrapper.login("cli").await? != LoginResult::Success {
+        scrapper.cancel_login().await?;
+        return Err(anyhow!("not logged in, run `decorp_bot login` first"));
+    }
+
+    let stats = scrapper.get_stats().await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        println!("{:#?}\n{:#?}", stats, stats.derived);
+    }
+
+    Ok(())
+}
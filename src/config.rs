@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use tracing::info;
+use serenity::http::Http;
+use serenity::model::user::User;
+
+use crate::Config;
+use crate::scrapper::SCREENSHOT_PAGES;
+
+const RUNTIME_CONFIG_FILE: &str = "runtime_config.json";
+const AUDIT_LOG_FILE: &str = "config_audit.log";
+
+/// Settings never shown by `!config get` or written to the audit log.
+const SECRET_KEYS: &[&str] = &["steam_password", "bot_token", "webhook_url", "cookies_key", "imap_password"];
+
+/// Settings from the config file, the environment and command line flags, later layers winning.
+/// Keys are upper case field names, values are strings, the same shape `envy` reads env vars in.
+pub struct ConfigLayers {
+    values: BTreeMap<String, String>,
+}
+
+fn flatten_scalar(value: &toml::Value) -> Result<String> {
+    Ok(match value {
+        toml::Value::String(s) => s.clone(),
+        toml::Value::Integer(i) => i.to_string(),
+        toml::Value::Float(f) => f.to_string(),
+        toml::Value::Boolean(b) => b.to_string(),
+        other => return Err(anyhow!("unsupported value {}", other)),
+    })
+}
+
+/// Turns a TOML value into the string envy would read from an env var. Arrays become comma
+/// separated lists, so their items can't contain commas. Empty arrays give `None`: envy reads
+/// `""` as a list holding one empty string, leaving the key out gives the field's default instead.
+fn flatten_toml(value: &toml::Value) -> Result<Option<String>> {
+    let items = match value {
+        toml::Value::Array(items) => items,
+        other => return flatten_scalar(other).map(Some),
+    };
+
+    let mut flat = vec![];
+    for item in items {
+        let item = flatten_scalar(item)?;
+        if item.contains(',') {
+            return Err(anyhow!("list items can't contain commas, got \"{}\"", item));
+        }
+        if !item.is_empty() {
+            flat.push(item);
+        }
+    }
+
+    Ok(if flat.is_empty() { None } else { Some(flat.join(",")) })
+}
+
+fn read_overlay(path: &Path) -> Result<BTreeMap<String, String>> {
+    match fs::read_to_string(path) {
+        Ok(str) => Ok(serde_json::from_str(&str)?),
+        Err(why) if why.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(why) => Err(anyhow!("could not read {}: {}", path.display(), why)),
+    }
+}
+
+impl ConfigLayers {
+    pub fn load(path: &str, overrides: &[(String, String)]) -> Result<Self> {
+        let mut values = BTreeMap::new();
+
+        match fs::read_to_string(path) {
+            Ok(str) => {
+                let table = toml::from_str::<toml::value::Table>(&str)
+                    .map_err(|why| anyhow!("{} is not valid TOML: {}", path, why))?;
+                for (key, value) in table {
+                    if let Some(value) = flatten_toml(&value).map_err(|why| anyhow!("{}: `{}`: {}", path, key, why))? {
+                        values.insert(key.to_uppercase(), value);
+                    }
+                }
+            },
+            Err(why) if why.kind() == ErrorKind::NotFound => {},
+            Err(why) => return Err(anyhow!("could not read {}: {}", path, why)),
+        }
+
+        values.extend(std::env::vars());
+        values.extend(overrides.iter().map(|(k, v)| (k.to_uppercase(), v.clone())));
+
+        Ok(ConfigLayers { values })
+    }
+
+    pub fn build(&self) -> Result<Config> {
+        let mut cfg = envy::from_iter::<_, Config>(self.values.clone()).map_err(|why| match why {
+            envy::Error::MissingValue(field) => anyhow!(
+                "missing required setting `{}`: set it in the config file, as the {} env var or with --{}",
+                field, field.to_uppercase(), field.replace('_', "-")
+            ),
+            envy::Error::Custom(msg) => anyhow!("invalid setting: {}", msg),
+        })?;
+
+        // an empty env var or `--watched-key-batches ""` still comes through as `[""]`, which
+        // would match every batch
+        cfg.watched_key_batches.retain(|batch| !batch.trim().is_empty());
+        Ok(cfg)
+    }
+}
+
+/// Where the config comes from, kept around so it can be reloaded at runtime. On top of the file,
+/// env and flag layers sits the runtime overlay written by `!config set`.
+#[derive(Clone)]
+pub struct ConfigSource {
+    pub path: String,
+    pub overrides: Vec<(String, String)>,
+}
+
+impl ConfigSource {
+    /// Returns the file/env/flag layers and the runtime overlay path, which lives in the data dir.
+    fn base(&self) -> Result<(ConfigLayers, std::path::PathBuf)> {
+        let layers = ConfigLayers::load(&self.path, &self.overrides)?;
+        let overlay_path = layers.build()?.data_path(RUNTIME_CONFIG_FILE);
+        Ok((layers, overlay_path))
+    }
+
+    pub fn load(&self) -> Result<Config> {
+        let (mut layers, overlay_path) = self.base()?;
+        layers.values.extend(read_overlay(&overlay_path)?);
+        layers.build()
+    }
+
+    /// Validates and persists a runtime override, returning the resulting config.
+    pub fn set(&self, key: &str, value: &str) -> Result<Config> {
+        if !Config::is_known_key(key) {
+            return Err(anyhow!("unknown setting `{}`", key));
+        }
+        // the runtime overlay is plaintext, secrets belong in the config file or environment
+        if SECRET_KEYS.contains(&key) {
+            return Err(anyhow!("`{}` is a secret and can't be set from chat, change it in the config file or environment", key));
+        }
+
+        let (mut layers, overlay_path) = self.base()?;
+        let mut overlay = read_overlay(&overlay_path)?;
+        overlay.insert(key.to_uppercase(), value.to_string());
+        layers.values.extend(overlay.clone());
+
+        let cfg = layers.build()?;
+        let errors = cfg.validate();
+        if !errors.is_empty() {
+            return Err(anyhow!(errors.join("\n")));
+        }
+
+        fs::write(&overlay_path, serde_json::to_vec_pretty(&overlay)?)?;
+        Ok(cfg)
+    }
+}
+
+fn check_url(errors: &mut Vec<String>, field: &str, value: &str) {
+    match reqwest::Url::parse(value) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {},
+        Ok(_) => errors.push(format!("`{}` must be an http(s) URL, got {}", field, value)),
+        Err(why) => errors.push(format!("`{}` is not a valid URL ({}): {}", field, why, value)),
+    }
+}
+
+impl Config {
+    fn is_known_key(key: &str) -> bool {
+        serde_json::to_value(Config::default()).unwrap().get(key).is_some()
+    }
+
+    /// Current value of a setting for display, with secrets hidden.
+    pub fn get_display(&self, key: &str) -> Option<String> {
+        let value = serde_json::to_value(self).unwrap().get(key)?.clone();
+        if SECRET_KEYS.contains(&key) {
+            return Some("<hidden>".to_string());
+        }
+
+        Some(match value {
+            serde_json::Value::String(s) => s,
+            other => other.to_string(),
+        })
+    }
+
+    /// Appends a config change to the audit log in the data dir.
+    pub fn audit(&self, user: &User, action: &str, key: &str, old: Option<String>, new: Option<String>) -> Result<()> {
+        let entry = serde_json::json!({
+            "at": Utc::now(),
+            "user_id": user.id.0,
+            "user": user.tag(),
+            "action": action,
+            "key": key,
+            "old": old,
+            "new": new,
+        });
+        info!(target: "config_audit", "{}", entry);
+
+        let mut file = OpenOptions::new().create(true).append(true).open(self.data_path(AUDIT_LOG_FILE))?;
+        writeln!(file, "{}", entry)?;
+        Ok(())
+    }
+
+    /// Checks the settings that can be checked offline. Returns one message per problem.
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        if self.bot_token.trim().is_empty() {
+            errors.push("`bot_token` is empty".to_string());
+        }
+        if self.prefix.is_empty() {
+            errors.push("`prefix` is empty".to_string());
+        }
+        if self.owner_id == 0 {
+            errors.push("`owner_id` must be set".to_string());
+        }
+        if self.role_id == 0 {
+            errors.push("`role_id` must be set".to_string());
+        }
+
+        check_url(&mut errors, "stats_url", &self.stats_url);
+        check_url(&mut errors, "session_probe_url", &self.session_probe_url);
+        check_url(&mut errors, "store_url", &self.store_url);
+        check_url(&mut errors, "community_url", &self.community_url);
+        if !self.key_report_url.is_empty() {
+            check_url(&mut errors, "key_report_url", &self.key_report_url);
+        }
+
+        match self.login_mode.as_str() {
+            "password" if self.steam_password.is_empty() => errors.push("`steam_password` is required with login_mode = \"password\"".to_string()),
+            "password" | "qr" => {},
+            other => errors.push(format!("`login_mode` must be \"password\" or \"qr\", got \"{}\"", other)),
+        }
+
+
+        if self.updates_channel_id != 0 && self.updates_interval_secs == 0 {
+            errors.push("`updates_interval_secs` must be greater than 0 when `updates_channel_id` is set".to_string());
+        }
+        if self.reviews_channel_id != 0 && self.app_id == 0 {
+            errors.push("`app_id` must be set when `reviews_channel_id` is set".to_string());
+        }
+        if self.steam_guard_attempts == 0 {
+            errors.push("`steam_guard_attempts` must be at least 1".to_string());
+        }
+        if let Err(why) = tracing_subscriber::EnvFilter::try_new(&self.log_level) {
+            errors.push(format!("`log_level` is not a valid filter ({}): {}", why, self.log_level));
+        }
+        if let Err(why) = crate::logging::parse_rotation(&self.log_rotation) {
+            errors.push(format!("`log_rotation`: {}", why));
+        }
+        if !self.report_screenshot_page.is_empty() && !SCREENSHOT_PAGES.contains(&self.report_screenshot_page.as_str()) {
+            errors.push(format!("`report_screenshot_page` must be one of {}, got {}", SCREENSHOT_PAGES.join(", "), self.report_screenshot_page));
+        }
+        if !self.chrome_ws_url.is_empty() && !(self.chrome_ws_url.starts_with("ws://") || self.chrome_ws_url.starts_with("wss://")) {
+            errors.push(format!("`chrome_ws_url` must be a ws:// or wss:// URL, got {}", self.chrome_ws_url));
+        }
+        if !self.chrome_path.is_empty() && !Path::new(&self.chrome_path).exists() {
+            errors.push(format!("`chrome_path` {} does not exist", self.chrome_path));
+        }
+        for (field, secs) in [("browser_timeout_secs", self.browser_timeout_secs), ("login_timeout_secs", self.login_timeout_secs), ("auth_code_timeout_secs", self.auth_code_timeout_secs)] {
+            if secs == 0 {
+                errors.push(format!("`{}` must be greater than 0", field));
+            }
+        }
+        if !self.imap_host.is_empty() && (self.imap_user.is_empty() || self.imap_password.is_empty()) {
+            errors.push("`imap_user` and `imap_password` are required when `imap_host` is set".to_string());
+        }
+
+        errors
+    }
+
+    /// Checks the bot token and that the configured channels are visible to the bot.
+    pub async fn validate_discord(&self) -> Vec<String> {
+        let http = Http::new_with_token(&self.bot_token);
+        if let Err(why) = http.get_current_user().await {
+            return vec![format!("`bot_token` was rejected by Discord: {}", why)];
+        }
+
+        let mut errors = vec![];
+        for (field, id) in [("updates_channel_id", self.updates_channel_id), ("reviews_channel_id", self.reviews_channel_id)] {
+            if id == 0 {
+                continue;
+            }
+            if let Err(why) = http.get_channel(id).await {
+                errors.push(format!("`{}` {} is not visible to the bot: {}", field, id, why));
+            }
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flatten(toml: &str) -> Result<Option<String>> {
+        flatten_toml(&toml::from_str::<toml::value::Table>(toml).unwrap()["value"])
+    }
+
+    #[test]
+    fn empty_arrays_are_left_out() {
+        assert_eq!(flatten("value = []").unwrap(), None);
+        assert_eq!(flatten("value = [\"\"]").unwrap(), None);
+    }
+
+    #[test]
+    fn arrays_are_joined_without_empty_items() {
+        assert_eq!(flatten("value = [\"Giveaway\", \"\", \"Press\"]").unwrap(), Some("Giveaway,Press".to_string()));
+    }
+
+    #[test]
+    fn array_items_with_commas_are_rejected() {
+        assert!(flatten("value = [\"Giveaway, 2022\"]").is_err());
+    }
+
+    #[test]
+    fn scalars_are_kept_as_is() {
+        assert_eq!(flatten("value = 300").unwrap(), Some("300".to_string()));
+        assert_eq!(flatten("value = \"\"").unwrap(), Some(String::new()));
+    }
+}
@@ -0,0 +1,404 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use aes_gcm::{Aes256Gcm, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam, CookieSameSite};
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::Config;
+
+const MAGIC: &[u8] = b"DCBC1";
+const NONCE_LEN: usize = 12;
+
+/// Key file in the data dir, used when neither `cookies_key` nor `cookies_key_path` is set.
+const DEFAULT_KEY_FILE: &str = "cookies.key";
+
+/// Creates a key file with a new random key, readable by the owner only. Returns false and leaves
+/// the file alone if one already exists: replacing the key would make the saved cookies unreadable.
+pub fn create_key_file(path: &Path) -> Result<bool> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut file = match options.open(path) {
+        Ok(file) => file,
+        Err(why) if why.kind() == ErrorKind::AlreadyExists => return Ok(false),
+        Err(why) => return Err(anyhow!("could not create cookies key file {}: {}", path.display(), why)),
+    };
+    writeln!(file, "{}", base64::encode(Aes256Gcm::generate_key(&mut OsRng)))?;
+
+    Ok(true)
+}
+
+/// Authenticated encryption for the cookie file. The key is 32 bytes, base64 encoded, taken
+/// from `cookies_key` or read from the file at `cookies_key_path`.
+pub struct CookieCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CookieCipher {
+    /// Without `cookies_key` or `cookies_key_path` a key file is generated in the data dir on the
+    /// first start, so the cookies never hit the disk in plaintext.
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        if !cfg.cookies_key.is_empty() {
+            return Self::from_base64(&cfg.cookies_key);
+        }
+
+        let path = if cfg.cookies_key_path.is_empty() {
+            let path = cfg.data_path(DEFAULT_KEY_FILE);
+            if create_key_file(&path)? {
+                info!("generated a cookies encryption key in {}", path.display());
+            }
+            path
+        } else {
+            PathBuf::from(&cfg.cookies_key_path)
+        };
+
+        let encoded = fs::read_to_string(&path)
+            .map_err(|why| anyhow!("could not read cookies key file {}: {}", path.display(), why))?;
+        Self::from_base64(&encoded)
+    }
+
+    fn from_base64(encoded: &str) -> Result<Self> {
+        let key = base64::decode(encoded.trim()).map_err(|why| anyhow!("cookies key is not valid base64: {}", why))?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow!("cookies key must be 32 bytes, got {}", key.len()))?;
+
+        Ok(CookieCipher { cipher })
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext).map_err(|_| anyhow!("cookie encryption failed"))?;
+
+        let mut res = MAGIC.to_vec();
+        res.extend_from_slice(&nonce);
+        res.extend_from_slice(&ciphertext);
+        Ok(res)
+    }
+
+    fn decrypt(&self, path: &str, data: &[u8]) -> Result<Vec<u8>> {
+        let data = &data[MAGIC.len()..];
+        if data.len() < NONCE_LEN {
+            return Err(anyhow!("cookie store {} is truncated", path));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("cookie store {} could not be decrypted: wrong key or the file was tampered with", path))
+    }
+}
+
+/// Writes to a temporary file next to `path` and renames it over, so a crash mid-write can't
+/// leave a truncated cookie file behind.
+fn write_atomic(path: &str, data: &[u8]) -> Result<()> {
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Reads and decrypts the cookie file. A missing file yields `None`. A plaintext JSON file from
+/// before encryption was set up is encrypted in place and returned as is.
+fn read_store(path: &str, cipher: &CookieCipher) -> Result<Option<Vec<u8>>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(why) if why.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(why) => return Err(anyhow!(why)),
+    };
+
+    if data.starts_with(MAGIC) {
+        return cipher.decrypt(path, &data).map(Some);
+    }
+
+    if serde_json::from_slice::<serde_json::Value>(&data).is_ok() {
+        info!("migrating plaintext cookie store {} to encrypted", path);
+        write_store(path, &data, cipher)?;
+        return Ok(Some(data));
+    }
+
+    Err(anyhow!("cookie store {} is neither encrypted nor valid JSON", path))
+}
+
+fn write_store(path: &str, plaintext: &[u8], cipher: &CookieCipher) -> Result<()> {
+    write_atomic(path, &cipher.encrypt(plaintext)?)
+}
+
+/// A cookie with all of its attributes. The serialized form matches Chrome's `Network.Cookie`,
+/// so cookie files written before this store existed still load.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    /// Unix timestamp in seconds, -1 for session cookies
+    pub expires: f64,
+    #[serde(default)]
+    pub http_only: bool,
+    #[serde(default)]
+    pub secure: bool,
+    #[serde(default)]
+    pub same_site: Option<CookieSameSite>,
+}
+
+impl StoredCookie {
+    fn is_expired(&self, now: f64) -> bool {
+        self.expires > 0.0 && self.expires <= now
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return false,
+        };
+
+        let domain = self.domain.trim_start_matches('.');
+        let domain_matches = host == domain || host.ends_with(&format!(".{}", domain));
+
+        domain_matches && url.path().starts_with(&self.path) && (!self.secure || url.scheme() == "https")
+    }
+
+    fn from_set_cookie(header: &HeaderValue, url: &Url) -> Option<Self> {
+        let parsed = cookie::Cookie::parse(header.to_str().ok()?).ok()?;
+
+        let expires = if let Some(max_age) = parsed.max_age() {
+            (Utc::now().timestamp() + max_age.whole_seconds()) as f64
+        } else if let Some(expires) = parsed.expires_datetime() {
+            expires.unix_timestamp() as f64
+        } else {
+            -1.0
+        };
+
+        Some(StoredCookie {
+            name: parsed.name().to_string(),
+            value: parsed.value().to_string(),
+            domain: parsed.domain().map_or_else(|| url.host_str().unwrap_or_default().to_string(), |d| format!(".{}", d.trim_start_matches('.'))),
+            path: parsed.path().unwrap_or("/").to_string(),
+            expires,
+            http_only: parsed.http_only().unwrap_or(false),
+            secure: parsed.secure().unwrap_or(false),
+            same_site: parsed.same_site().map(|s| match s {
+                cookie::SameSite::Strict => CookieSameSite::Strict,
+                cookie::SameSite::Lax => CookieSameSite::Lax,
+                cookie::SameSite::None => CookieSameSite::None,
+            }),
+        })
+    }
+}
+
+impl From<&Cookie> for StoredCookie {
+    fn from(c: &Cookie) -> Self {
+        StoredCookie {
+            name: c.name.clone(),
+            value: c.value.clone(),
+            domain: c.domain.clone(),
+            path: c.path.clone(),
+            expires: c.expires,
+            http_only: c.http_only,
+            secure: c.secure,
+            same_site: c.same_site.clone(),
+        }
+    }
+}
+
+impl From<&StoredCookie> for CookieParam {
+    fn from(c: &StoredCookie) -> Self {
+        CookieParam {
+            name: c.name.clone(),
+            value: c.value.clone(),
+            url: None,
+            domain: Some(c.domain.clone()),
+            path: Some(c.path.clone()),
+            expires: if c.expires > 0.0 { Some(c.expires) } else { None },
+            priority: None,
+            same_party: None,
+            source_scheme: None,
+            source_port: None,
+            http_only: Some(c.http_only),
+            secure: Some(c.secure),
+            same_site: c.same_site.clone(),
+            partition_key: None,
+        }
+    }
+}
+
+/// On-disk cookie store used both as the reqwest cookie provider and as the source of cookies
+/// for headless Chrome, encrypted at rest. Cookies set by responses are written back to disk
+/// immediately.
+pub struct PersistentCookieStore {
+    path: String,
+    cipher: CookieCipher,
+    cookies: Mutex<Vec<StoredCookie>>,
+}
+
+impl PersistentCookieStore {
+    pub fn load(path: String, cipher: CookieCipher) -> Result<Self> {
+        let cookies = match read_store(&path, &cipher)? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => vec![],
+        };
+
+        Ok(PersistentCookieStore {
+            path,
+            cipher,
+            cookies: Mutex::new(cookies),
+        })
+    }
+
+    fn save(&self, cookies: &[StoredCookie]) -> Result<()> {
+        write_store(&self.path, &serde_json::to_vec(cookies)?, &self.cipher)
+    }
+
+    /// Replaces the whole store with the cookies currently held by the browser.
+    pub fn replace_from_browser(&self, cookies: &[Cookie]) -> Result<()> {
+        let mut lock = self.cookies.lock().unwrap();
+        *lock = cookies.iter().map(StoredCookie::from).collect();
+        self.save(&lock)
+    }
+
+    pub fn to_cookie_params(&self) -> Vec<CookieParam> {
+        let now = Utc::now().timestamp() as f64;
+        self.cookies.lock().unwrap().iter()
+            .filter(|c| !c.is_expired(now))
+            .map(CookieParam::from)
+            .collect()
+    }
+
+    /// Expiry of the named cookie as a unix timestamp, `None` if it's missing or a session cookie.
+    pub fn expiry(&self, name: &str) -> Option<f64> {
+        self.cookies.lock().unwrap().iter()
+            .filter(|c| c.name == name && c.expires > 0.0)
+            .map(|c| c.expires)
+            .reduce(f64::min)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        let mut lock = self.cookies.lock().unwrap();
+        lock.clear();
+        self.save(&lock)
+    }
+}
+
+impl reqwest::cookie::CookieStore for PersistentCookieStore {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let mut lock = self.cookies.lock().unwrap();
+        let now = Utc::now().timestamp() as f64;
+        let mut changed = false;
+
+        for new in cookie_headers.filter_map(|h| StoredCookie::from_set_cookie(h, url)) {
+            lock.retain(|c| !(c.name == new.name && c.domain == new.domain && c.path == new.path));
+            if !new.is_expired(now) {
+                lock.push(new);
+            }
+            changed = true;
+        }
+
+        if changed {
+            if let Err(why) = self.save(&lock) {
+                error!("failed to persist cookies: {:?}", why);
+            }
+        }
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let now = Utc::now().timestamp() as f64;
+        let header = self.cookies.lock().unwrap().iter()
+            .filter(|c| !c.is_expired(now) && c.matches(url))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if header.is_empty() {
+            return None;
+        }
+
+        HeaderValue::from_str(&header).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher() -> CookieCipher {
+        CookieCipher::from_base64(&base64::encode([7u8; 32])).unwrap()
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("decorp_bot_{}_{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let path = temp_path("round_trip.json");
+        write_store(&path, b"[]", &cipher()).unwrap();
+
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+        assert_eq!(read_store(&path, &cipher()).unwrap(), Some(b"[]".to_vec()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn flipped_byte_is_reported_as_tampered() {
+        let path = temp_path("tampered.json");
+        write_store(&path, b"[]", &cipher()).unwrap();
+        let mut data = fs::read(&path).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        fs::write(&path, data).unwrap();
+
+        let why = read_store(&path, &cipher()).unwrap_err();
+
+        assert!(why.to_string().contains("tampered"), "{}", why);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wrong_key_does_not_decrypt() {
+        let path = temp_path("wrong_key.json");
+        write_store(&path, b"[]", &cipher()).unwrap();
+        let other = CookieCipher::from_base64(&base64::encode([8u8; 32])).unwrap();
+
+        assert!(read_store(&path, &other).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn plaintext_store_is_encrypted_in_place() {
+        let path = temp_path("plaintext.json");
+        fs::write(&path, b"[]").unwrap();
+
+        assert_eq!(read_store(&path, &cipher()).unwrap(), Some(b"[]".to_vec()));
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+        assert_eq!(read_store(&path, &cipher()).unwrap(), Some(b"[]".to_vec()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn existing_key_file_is_kept() {
+        let path = PathBuf::from(temp_path("cookies.key"));
+
+        assert!(create_key_file(&path).unwrap());
+        let key = fs::read_to_string(&path).unwrap();
+        assert!(!create_key_file(&path).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), key);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use mailparse::ParsedMail;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tokio::{task, time};
+
+use crate::Config;
+
+/// Steam Guard codes use this alphabet, which conveniently excludes vowels so words like
+/// "STEAM" in the email body never match.
+static CODE_PATTERN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?m)(?:^|>)\s*([23456789BCDFGHJKMNPQRTVWXY]{5})\s*(?:$|<)").unwrap()
+});
+
+/// Looks for the code in the decoded text parts, Steam sends them quoted-printable or base64
+/// encoded so the raw message rarely contains the code as is.
+fn find_code(raw: &[u8]) -> Option<String> {
+    let mail = mailparse::parse_mail(raw).ok()?;
+    find_code_in(&mail)
+}
+
+fn find_code_in(part: &ParsedMail) -> Option<String> {
+    if !part.subparts.is_empty() {
+        return part.subparts.iter().find_map(find_code_in);
+    }
+    if !part.ctype.mimetype.starts_with("text/") {
+        return None;
+    }
+
+    let body = part.get_body().ok()?;
+    CODE_PATTERN.captures(&body).map(|c| c[1].to_string())
+}
+
+#[derive(Clone)]
+pub struct ImapSettings {
+    host: String,
+    port: u16,
+    tls: bool,
+    user: String,
+    password: String,
+    folder: String,
+    timeout: Duration,
+}
+
+impl ImapSettings {
+    pub fn from_config(cfg: &Config) -> Option<Self> {
+        if cfg.imap_host.is_empty() {
+            return None;
+        }
+
+        Some(ImapSettings {
+            host: cfg.imap_host.clone(),
+            port: cfg.imap_port,
+            tls: cfg.imap_tls,
+            user: cfg.imap_user.clone(),
+            password: cfg.imap_password.clone(),
+            folder: cfg.imap_folder.clone(),
+            timeout: Duration::from_secs(cfg.imap_timeout_secs),
+        })
+    }
+
+    /// Polls the mailbox until a Steam Guard email received after `since` shows up, marks it
+    /// read and returns the code.
+    pub async fn wait_for_code(&self, since: DateTime<Utc>) -> Result<String> {
+        let deadline = time::Instant::now() + self.timeout;
+
+        loop {
+            let settings = self.clone();
+            if let Some(code) = task::spawn_blocking(move || settings.fetch_code(since)).await?? {
+                return Ok(code);
+            }
+
+            if time::Instant::now() >= deadline {
+                return Err(anyhow!("no Steam Guard email received within {:?}", self.timeout));
+            }
+
+            time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+
+    fn fetch_code(&self, since: DateTime<Utc>) -> Result<Option<String>> {
+        if self.tls {
+            let tls = native_tls::TlsConnector::builder().build()?;
+            let client = imap::connect((self.host.as_str(), self.port), &self.host, &tls)?;
+            self.fetch_code_with(client, since)
+        } else {
+            let mut client = imap::Client::new(TcpStream::connect((self.host.as_str(), self.port))?);
+            client.read_greeting()?;
+            self.fetch_code_with(client, since)
+        }
+    }
+
+    fn fetch_code_with<T: Read + Write>(&self, client: imap::Client<T>, since: DateTime<Utc>) -> Result<Option<String>> {
+        let mut session = client.login(&self.user, &self.password).map_err(|(why, _)| why)?;
+        session.select(&self.folder)?;
+
+        let mut newest: Option<(DateTime<Utc>, u32, String)> = None;
+
+        for seq in session.search("UNSEEN FROM \"steampowered.com\"")? {
+            let fetches = session.fetch(seq.to_string(), "(INTERNALDATE BODY.PEEK[])")?;
+            for m in fetches.iter() {
+                let received = match m.internal_date() {
+                    Some(date) => date.with_timezone(&Utc),
+                    None => continue,
+                };
+                // allow for clock drift between us and the mail server
+                if received < since - chrono::Duration::minutes(1) || newest.as_ref().map_or(false, |(at, _, _)| *at > received) {
+                    continue;
+                }
+
+                if let Some(code) = m.body().and_then(find_code) {
+                    newest = Some((received, seq, code));
+                }
+            }
+        }
+
+        if let Some((_, seq, _)) = &newest {
+            session.store(seq.to_string(), "+FLAGS (\\Seen)")?;
+        }
+        session.logout()?;
+
+        Ok(newest.map(|(_, _, code)| code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    const QUOTED_PRINTABLE: &[u8] = include_bytes!("../tests/fixtures/steam_guard_qp.eml");
+    const BASE64: &[u8] = include_bytes!("../tests/fixtures/steam_guard_base64.eml");
+
+    /// A single-connection IMAP server with one unseen message in its inbox. Reports every
+    /// command it receives.
+    fn serve(mail: &'static [u8], internal_date: &'static str) -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(b"* OK IMAP4rev1 stand-in ready\r\n").unwrap();
+
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let mut parts = line.trim_end().splitn(3, ' ');
+                let tag = parts.next().unwrap_or_default();
+                let command = parts.next().unwrap_or_default().to_uppercase();
+                let _ = tx.send(format!("{} {}", command, parts.next().unwrap_or_default()));
+
+                let mut response = Vec::new();
+                match command.as_str() {
+                    "LOGIN" => write!(response, "{} OK LOGIN completed\r\n", tag),
+                    "SELECT" => write!(response, "* FLAGS (\\Seen)\r\n* 1 EXISTS\r\n* 0 RECENT\r\n{} OK [READ-WRITE] SELECT completed\r\n", tag),
+                    "SEARCH" => write!(response, "* SEARCH 1\r\n{} OK SEARCH completed\r\n", tag),
+                    "FETCH" => {
+                        write!(response, "* 1 FETCH (INTERNALDATE \"{}\" BODY[] {{{}}}\r\n", internal_date, mail.len()).unwrap();
+                        response.extend_from_slice(mail);
+                        write!(response, ")\r\n{} OK FETCH completed\r\n", tag)
+                    },
+                    "STORE" => write!(response, "* 1 FETCH (FLAGS (\\Seen))\r\n{} OK STORE completed\r\n", tag),
+                    "LOGOUT" => write!(response, "* BYE logging out\r\n{} OK LOGOUT completed\r\n", tag),
+                    _ => write!(response, "{} BAD unknown command\r\n", tag),
+                }.unwrap();
+                stream.write_all(&response).unwrap();
+
+                if command == "LOGOUT" {
+                    break;
+                }
+            }
+        });
+
+        (port, rx)
+    }
+
+    fn settings(port: u16) -> ImapSettings {
+        ImapSettings {
+            host: "127.0.0.1".to_string(),
+            port,
+            tls: false,
+            user: "decorp".to_string(),
+            password: "hunter2".to_string(),
+            folder: "INBOX".to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    fn since(at: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(at).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn finds_code_in_quoted_printable_mail() {
+        assert_eq!(find_code(QUOTED_PRINTABLE).as_deref(), Some("F4KTV"));
+    }
+
+    #[test]
+    fn finds_code_in_base64_mail() {
+        assert_eq!(find_code(BASE64).as_deref(), Some("F4KTV"));
+    }
+
+    #[test]
+    fn fetches_code_and_marks_mail_read() {
+        let (port, commands) = serve(QUOTED_PRINTABLE, "18-Oct-2026 12:00:00 +0000");
+
+        let code = settings(port).fetch_code(since("2026-10-18T11:59:30Z")).unwrap();
+
+        assert_eq!(code.as_deref(), Some("F4KTV"));
+        let commands = commands.try_iter().collect::<Vec<_>>();
+        assert!(commands.iter().any(|c| c.starts_with("SEARCH UNSEEN FROM")));
+        assert!(commands.iter().any(|c| c.starts_with("STORE 1 +FLAGS")));
+    }
+
+    #[test]
+    fn ignores_mail_from_before_the_login() {
+        let (port, commands) = serve(BASE64, "18-Oct-2026 11:00:00 +0000");
+
+        let code = settings(port).fetch_code(since("2026-10-18T12:00:00Z")).unwrap();
+
+        assert_eq!(code, None);
+        assert!(!commands.try_iter().any(|c| c.starts_with("STORE")));
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fs;
 use std::ops::Add;
 use std::sync::Arc;
@@ -6,74 +7,174 @@ use std::time::Duration;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serenity::futures::AsyncWriteExt;
-use serenity::http::{CacheHttp, Http};
+use serenity::http::{AttachmentType, CacheHttp, Http};
 use serenity::model::id::ChannelId;
 use serenity::prelude::TypeMap;
 use tokio::{task, time};
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use similar::{ChangeTag, TextDiff};
+use chrono::{NaiveDate, Utc};
+use tracing::{debug, error, warn};
 
 use crate::bot::{Bot, IntervalStarted};
 use crate::Config;
-use crate::scrapper::{Scrapper, Stats};
-
-pub fn start_interval(cfg: Config, scrapper: Arc<RwLock<Scrapper>>, http: Arc<Http>, data: Arc<RwLock<TypeMap>>) {
+use crate::keys::{describe_batch_changes, describe_watched_activations};
+use crate::players::PlayerTracker;
+use crate::redact::Redactor;
+use crate::report::daily_report;
+use crate::scrape_error::ScrapeError;
+use crate::session::notify_owner;
+use crate::scrapper::Stats;
+use crate::scrapper_actor::ScrapperHandle;
+use crate::utils::{fit_lines, MESSAGE_LIMIT};
+
+/// What the interval remembers between ticks. It outlives the task, so restarting the interval
+/// after a config change doesn't post the full stats as a diff or skip a daily report.
+pub struct IntervalState {
+    last_stats: Stats,
+    last_stats_str: String,
+    last_date: NaiveDate,
+}
+
+impl Default for IntervalState {
+    fn default() -> Self {
+        IntervalState {
+            last_stats: Stats::default(),
+            last_stats_str: String::new(),
+            last_date: Utc::now().naive_utc().date(),
+        }
+    }
+}
+
+/// Whether `new` changes anything a running interval was started with.
+pub fn settings_changed(old: &Config, new: &Config) -> bool {
+    old.updates_channel_id != new.updates_channel_id
+        || old.updates_interval_secs != new.updates_interval_secs
+        || old.watched_key_batches != new.watched_key_batches
+        || old.report_screenshot_page != new.report_screenshot_page
+        || old.owner_id != new.owner_id
+}
+
+pub fn start_interval(cfg: Config, scrapper: ScrapperHandle, http: Arc<Http>, data: Arc<RwLock<TypeMap>>) -> Option<JoinHandle<()>> {
     let ch_id = ChannelId(cfg.updates_channel_id);
 
     if ch_id == 0 {
-        return
+        return None
     }
 
     let mut interval = time::interval(Duration::from_secs(cfg.updates_interval_secs));
 
-    task::spawn(async move {
-        {
+    Some(task::spawn(async move {
+        let (players, shared_state) = {
             let mut lock = data.write().await;
             lock.insert::<IntervalStarted>(Arc::new(true));
-        }
-
-        let mut last_stats = Stats::default();
-        let mut last_stats_str = String::new();
+            (lock.get::<PlayerTracker>().unwrap().clone(), lock.get::<IntervalState>().unwrap().clone())
+        };
 
         'forever: loop {
             interval.tick().await;
-            let res = async {
-                let mut scrapper = scrapper.write().await;
-                scrapper.get_stats().await
-            }.await;
+            let res = scrapper.get_stats().await;
+            // secrets can change on a config reload without restarting the interval
+            let redactor = data.read().await.get::<Redactor>().unwrap().clone();
+            let mut state = shared_state.lock().await;
 
             let (msg, err) = match res {
                 Ok(stats) => {
-                    if stats == last_stats {
-                        println!("stats haven't changed");
+                    let now = Utc::now();
+                    let today = now.naive_utc().date();
+
+                    let peak = players.write().await.record(stats.current_players, now);
+                    match peak {
+                        Ok(Some(peak)) => {
+                            send(&http, &redactor, ch_id, format!("New all-time peak: **{}** concurrent players!", peak.players)).await;
+                        },
+                        Ok(None) => {},
+                        Err(why) => error!("failed to save player stats: {:?}", why),
+                    }
+
+                    if today != state.last_date {
+                        // `last_stats` is still the last scrape of the day being reported on
+                        let day_stats = (state.last_stats != Stats::default()).then(|| &state.last_stats);
+                        let mut report = daily_report(state.last_date, day_stats, &*players.read().await).into_iter();
+                        if let Some(first) = report.next() {
+                            match report_screenshot(&cfg, &scrapper).await {
+                                Some(png) => send_with_file(&http, &redactor, ch_id, first, png, &format!("{}.png", cfg.report_screenshot_page)).await,
+                                None => send(&http, &redactor, ch_id, first).await,
+                            }
+                        }
+                        for part in report {
+                            send(&http, &redactor, ch_id, part).await;
+                        }
+                        state.last_date = today;
+                    }
+
+                    if state.last_stats != Stats::default() {
+                        if let Some(change) = state.last_stats.store.describe_price_change(&stats.store) {
+                            send(&http, &redactor, ch_id, change).await;
+                        }
+
+                        if let Some(changes) = describe_batch_changes(&state.last_stats.key_batches, &stats.key_batches) {
+                            send(&http, &redactor, ch_id, changes).await;
+                        }
+
+                        for alert in describe_watched_activations(&cfg.watched_key_batches, &state.last_stats.key_batches, &stats.key_batches) {
+                            send(&http, &redactor, ch_id, alert).await;
+                        }
+                    }
+
+                    if stats == state.last_stats {
+                        debug!("stats haven't changed");
                         continue 'forever;
                     }
 
-                    let stats_str = format!("{:#?}", stats);
+                    let stats_str = format!("{:#?}\n{:#?}", stats, stats.derived);
 
-                    let diff = TextDiff::from_lines(&last_stats_str, &stats_str);
-                    let diff_str = diff.iter_all_changes().map(|change| {
+                    let diff = TextDiff::from_lines(&state.last_stats_str, &stats_str);
+                    let mut lines = diff.iter_all_changes().map(|change| {
                         let sign = match change.tag() {
                             ChangeTag::Delete => "-",
                             ChangeTag::Insert => "+",
                             ChangeTag::Equal => " ",
                         };
                         format!("{}{}", sign, change)
-                    }).collect::<Vec<_>>().join("");
+                    }).collect::<Vec<_>>();
 
-                    last_stats = stats.clone();
-                    last_stats_str = stats_str;
+                    // over Discord's limit the unchanged lines go first, then whatever still doesn't fit
+                    let budget = MESSAGE_LIMIT - "Stats changed: ```diff\n```".len();
+                    if lines.iter().map(|l| l.chars().count()).sum::<usize>() > budget {
+                        lines.retain(|l| !l.starts_with(' '));
+                    }
+                    let diff_str = fit_lines(&lines, budget);
+
+                    state.last_stats = stats.clone();
+                    state.last_stats_str = stats_str;
 
                     (format!("Stats changed: ```diff\n{}```", diff_str), false)
                 },
-                Err(why) => {
-                    (format!("failed to get stats: {:?}", why), true)
+                Err(why) => match ScrapeError::find(&why) {
+                    Some(e) if e.is_transient() => {
+                        warn!("failed to get stats, retrying next time: {:?}", why);
+                        continue 'forever;
+                    },
+                    Some(e) => {
+                        if e.needs_login() && cfg.owner_id != 0 {
+                            let msg = redactor.redact(&format!("Updates stopped: {}. {}", e, e.recovery()));
+                            if let Err(why) = notify_owner(&http, cfg.owner_id, msg).await {
+                                warn!("failed to notify owner: {:?}", why);
+                            }
+                        }
+                        (format!("Updates stopped: {}. {}", e, e.recovery()), true)
+                    },
+                    None => {
+                        // the unredacted error only goes to the log
+                        error!("failed to get stats: {:?}", why);
+                        (format!("failed to get stats: {:#}", why), true)
+                    },
                 }
             };
 
-            if let Err(why) = ch_id.send_message(&http, |m| m.content(msg)).await {
-                println!("failed to send message: {:?}", why);
-            }
+            send(&http, &redactor, ch_id, msg).await;
 
             if err {
                 break 'forever;
@@ -84,5 +185,34 @@ pub fn start_interval(cfg: Config, scrapper: Arc<RwLock<Scrapper>>, http: Arc<Ht
             let mut lock = data.write().await;
             lock.insert::<IntervalStarted>(Arc::new(false));
         }
-    });
-}
\ No newline at end of file
+    }))
+}
+
+async fn report_screenshot(cfg: &Config, scrapper: &ScrapperHandle) -> Option<Vec<u8>> {
+    if cfg.report_screenshot_page.is_empty() {
+        return None;
+    }
+
+    match scrapper.screenshot(cfg.report_screenshot_page.clone(), None).await {
+        Ok(png) => Some(png),
+        Err(why) => {
+            warn!("failed to take report screenshot: {:?}", why);
+            None
+        }
+    }
+}
+
+async fn send_with_file(http: &Http, redactor: &Redactor, ch_id: ChannelId, msg: String, data: Vec<u8>, filename: &str) {
+    let msg = redactor.redact(&msg);
+    let file = AttachmentType::Bytes { data: Cow::from(data), filename: filename.to_string() };
+    if let Err(why) = ch_id.send_message(http, |m| m.content(msg).add_file(file)).await {
+        warn!("failed to send message: {:?}", why);
+    }
+}
+
+async fn send(http: &Http, redactor: &Redactor, ch_id: ChannelId, msg: String) {
+    let msg = redactor.redact(&msg);
+    if let Err(why) = ch_id.send_message(http, |m| m.content(msg)).await {
+        warn!("failed to send message: {:?}", why);
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use scraper::{Html, Selector};
+
+use crate::scrape_error::ScrapeError;
+
+/// Parses the partner key activation report into activations per key request/batch.
+/// Only the table with an activations column is read, the page has other tables for layout.
+/// The batch name is taken from the first column and the activation count from the last one.
+pub fn parse_key_report(text: &str) -> Result<BTreeMap<String, i32>> {
+    let document = Html::parse_document(text);
+    let table_selector = Selector::parse("table").unwrap();
+    let header_selector = Selector::parse("th").unwrap();
+    let row_selector = Selector::parse("tr").unwrap();
+    let cell_selector = Selector::parse("td").unwrap();
+
+    let table = document.select(&table_selector)
+        .find(|table| table.select(&header_selector).any(|th| th.text().collect::<String>().to_lowercase().contains("activat")))
+        .ok_or_else(|| ScrapeError::LayoutChanged { field: "key activation table".to_string() })?;
+
+    let mut res = BTreeMap::new();
+    for row in table.select(&row_selector) {
+        let cells = row.select(&cell_selector)
+            .map(|c| c.text().collect::<String>().trim().to_string())
+            .collect::<Vec<_>>();
+
+        if cells.len() < 2 {
+            continue;
+        }
+
+        let digits = cells[cells.len() - 1].chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+        if digits.is_empty() {
+            continue;
+        }
+
+        *res.entry(cells[0].clone()).or_insert(0) += digits.parse::<i32>()?;
+    }
+
+    if res.is_empty() {
+        return Err(anyhow!("no key batches found in key activation report"));
+    }
+
+    Ok(res)
+}
+
+/// Most batches `describe_batch_changes` lists, the rest are only counted.
+const MAX_LISTED_BATCHES: usize = 15;
+
+/// Lists the batches whose activation count changed. `Stats` only shows the totals, this is how
+/// the updates channel sees which batches moved.
+pub fn describe_batch_changes(old: &BTreeMap<String, i32>, new: &BTreeMap<String, i32>) -> Option<String> {
+    let changes = new.iter()
+        .filter(|(name, count)| old.get(*name) != Some(*count))
+        .map(|(name, count)| format!("`{}`: {} -> {}", name, old.get(name).copied().unwrap_or(0), count))
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return None;
+    }
+
+    let mut res = format!("Key activations changed:\n{}", changes[..changes.len().min(MAX_LISTED_BATCHES)].join("\n"));
+    if changes.len() > MAX_LISTED_BATCHES {
+        res.push_str(&format!("\n...and {} more", changes.len() - MAX_LISTED_BATCHES));
+    }
+    Some(res)
+}
+
+/// Describes new activations for the watched batches. A watched name matches any batch containing
+/// it, an empty one matches nothing.
+pub fn describe_watched_activations(watched: &[String], old: &BTreeMap<String, i32>, new: &BTreeMap<String, i32>) -> Vec<String> {
+    new.iter()
+        .filter(|(name, _)| watched.iter().any(|w| !w.is_empty() && name.contains(w.as_str())))
+        .filter_map(|(name, &count)| {
+            let before = old.get(name).copied().unwrap_or(0);
+            if count > before {
+                Some(format!("**{}** new key activation(s) from `{}` (total {})", count - before, name, count))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Result};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::Config;
+
+const LOG_DIR: &str = "logs";
+const LOG_FILE_PREFIX: &str = "decorp_bot.log";
+
+/// `None` for "off", which disables the log file.
+pub fn parse_rotation(rotation: &str) -> Result<Option<Rotation>> {
+    Ok(Some(match rotation {
+        "off" => return Ok(None),
+        "minutely" => Rotation::MINUTELY,
+        "hourly" => Rotation::HOURLY,
+        "daily" => Rotation::DAILY,
+        "never" => Rotation::NEVER,
+        other => return Err(anyhow!("unknown log rotation \"{}\", expected off, minutely, hourly, daily or never", other)),
+    }))
+}
+
+/// Logs to stderr, so `scrape --json` output stays clean, and to rotating files under the data
+/// dir. The returned guard flushes the file writer on drop, keep it alive until exit.
+pub fn init(cfg: &Config) -> Result<Option<WorkerGuard>> {
+    let filter = EnvFilter::try_new(&cfg.log_level)?;
+
+    let console = fmt::layer().with_writer(std::io::stderr);
+    let console = if cfg.log_json { console.json().boxed() } else { console.boxed() };
+
+    let (file, guard) = match parse_rotation(&cfg.log_rotation)? {
+        Some(rotation) => {
+            let appender = RollingFileAppender::new(rotation, cfg.data_path(LOG_DIR), LOG_FILE_PREFIX);
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+
+            let file = fmt::layer().with_writer(writer).with_ansi(false);
+            let file = if cfg.log_json { file.json().boxed() } else { file.boxed() };
+            (Some(file), Some(guard))
+        },
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(file)
+        .with(filter)
+        .try_init()?;
+
+    Ok(guard)
+}
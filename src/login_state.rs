@@ -0,0 +1,165 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoginState {
+    LoggedOut,
+    LaunchingBrowser,
+    AwaitingCaptcha,
+    AwaitingSteamGuard,
+    AwaitingQrApproval,
+    LoggedIn,
+    Expired,
+    Failed(String),
+}
+
+impl fmt::Display for LoginState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginState::LoggedOut => write!(f, "logged out"),
+            LoginState::LaunchingBrowser => write!(f, "launching browser"),
+            LoginState::AwaitingCaptcha => write!(f, "awaiting captcha answer"),
+            LoginState::AwaitingSteamGuard => write!(f, "awaiting Steam Guard code"),
+            LoginState::AwaitingQrApproval => write!(f, "awaiting QR code approval"),
+            LoginState::LoggedIn => write!(f, "logged in"),
+            LoginState::Expired => write!(f, "session expired"),
+            LoginState::Failed(reason) => write!(f, "failed: {}", reason),
+        }
+    }
+}
+
+impl LoginState {
+    fn can_transition_to(&self, to: &LoginState) -> bool {
+        use LoginState::*;
+
+        match (self, to) {
+            (_, LoggedOut) => true,
+            (LoggedOut | Expired | Failed(_), LaunchingBrowser | AwaitingQrApproval) => true,
+            (LoggedOut | LaunchingBrowser | AwaitingCaptcha | AwaitingSteamGuard | AwaitingQrApproval | Expired | Failed(_), LoggedIn) => true,
+            (LaunchingBrowser | AwaitingCaptcha, AwaitingSteamGuard) => true,
+            (LaunchingBrowser, AwaitingCaptcha) => true,
+            (LoggedIn, Expired) => true,
+            (_, Failed(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_login_in_progress(&self) -> bool {
+        matches!(self, LoginState::LaunchingBrowser | LoginState::AwaitingCaptcha | LoginState::AwaitingSteamGuard | LoginState::AwaitingQrApproval)
+    }
+}
+
+#[derive(Clone)]
+pub struct ScrapeOutcome {
+    pub at: DateTime<Utc>,
+    pub result: Result<(), String>,
+}
+
+/// Login state and last scrape result, shared outside of the scrapper task so `!status` can
+/// answer while a login is in progress.
+#[derive(Clone)]
+pub struct ScrapperStatus {
+    state: LoginState,
+    since: DateTime<Utc>,
+    last_scrape: Option<ScrapeOutcome>,
+}
+
+impl Default for ScrapperStatus {
+    fn default() -> Self {
+        ScrapperStatus {
+            state: LoginState::LoggedOut,
+            since: Utc::now(),
+            last_scrape: None,
+        }
+    }
+}
+
+impl ScrapperStatus {
+    pub fn state(&self) -> &LoginState {
+        &self.state
+    }
+
+    pub fn since(&self) -> DateTime<Utc> {
+        self.since
+    }
+
+    pub fn last_scrape(&self) -> Option<&ScrapeOutcome> {
+        self.last_scrape.as_ref()
+    }
+
+    pub fn transition(&mut self, to: LoginState) -> Result<()> {
+        if self.state == to {
+            return Ok(());
+        }
+
+        if !self.state.can_transition_to(&to) {
+            return Err(anyhow!("invalid login state transition: {} -> {}", self.state, to));
+        }
+
+        self.state = to;
+        self.since = Utc::now();
+        Ok(())
+    }
+
+    pub fn record_scrape(&mut self, result: Result<(), String>) {
+        self.last_scrape = Some(ScrapeOutcome { at: Utc::now(), result });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::LoginState::*;
+
+    #[test]
+    fn anything_can_log_out_or_fail() {
+        for from in [LoggedOut, LaunchingBrowser, AwaitingCaptcha, AwaitingSteamGuard, AwaitingQrApproval, LoggedIn, Expired, Failed("boom".to_string())] {
+            assert!(from.can_transition_to(&LoggedOut), "{} -> logged out", from);
+            assert!(from.can_transition_to(&Failed("boom".to_string())), "{} -> failed", from);
+        }
+    }
+
+    #[test]
+    fn logins_start_only_when_idle() {
+        for from in [LoggedOut, Expired, Failed("boom".to_string())] {
+            assert!(from.can_transition_to(&LaunchingBrowser));
+            assert!(from.can_transition_to(&AwaitingQrApproval));
+        }
+        for from in [LaunchingBrowser, AwaitingCaptcha, AwaitingSteamGuard, AwaitingQrApproval, LoggedIn] {
+            assert!(!from.can_transition_to(&LaunchingBrowser), "{} -> launching browser", from);
+        }
+    }
+
+    #[test]
+    fn browser_login_prompts_follow_the_form() {
+        assert!(LaunchingBrowser.can_transition_to(&AwaitingCaptcha));
+        assert!(LaunchingBrowser.can_transition_to(&AwaitingSteamGuard));
+        assert!(AwaitingCaptcha.can_transition_to(&AwaitingSteamGuard));
+        assert!(!AwaitingSteamGuard.can_transition_to(&AwaitingCaptcha));
+        assert!(!LoggedOut.can_transition_to(&AwaitingSteamGuard));
+        assert!(!AwaitingQrApproval.can_transition_to(&AwaitingSteamGuard));
+    }
+
+    #[test]
+    fn only_a_live_session_expires() {
+        assert!(LoggedIn.can_transition_to(&Expired));
+        assert!(!LoggedOut.can_transition_to(&Expired));
+        assert!(!AwaitingSteamGuard.can_transition_to(&Expired));
+        assert!(!Expired.can_transition_to(&AwaitingCaptcha));
+    }
+
+    #[test]
+    fn invalid_transitions_leave_the_state_alone() {
+        let mut status = ScrapperStatus::default();
+
+        assert!(status.transition(Expired).is_err());
+        assert_eq!(status.state(), &LoggedOut);
+
+        status.transition(LaunchingBrowser).unwrap();
+        status.transition(AwaitingSteamGuard).unwrap();
+        status.transition(LoggedIn).unwrap();
+        assert_eq!(status.state(), &LoggedIn);
+    }
+}
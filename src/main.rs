@@ -1,9 +1,10 @@
-use std::fs;
 use std::ops::Add;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
+use tracing::{info, warn};
 use serde::{Deserialize, Serialize};
 use serenity::futures::TryStreamExt;
 use serenity::http::CacheHttp;
@@ -12,21 +13,59 @@ use tokio::{task, time};
 use tokio::sync::RwLock;
 
 use crate::bot::Bot;
+use crate::cli::CliArgs;
+use crate::bot::{IntervalHandle, SessionMonitorHandle};
+use crate::config::ConfigSource;
 use crate::interval::start_interval;
+use crate::players::PlayerTracker;
 use crate::scrapper::{LoginResult, Scrapper, Stats};
+use crate::scrapper_actor::ScrapperHandle;
 
 mod scrapper;
+mod scrapper_actor;
+mod scrape_error;
+mod redact;
+mod logging;
+mod browser;
 mod bot;
 mod interval;
 mod utils;
+mod players;
+mod report;
+mod reviews;
+mod store_page;
+mod keys;
+mod metrics;
+mod cookie_store;
+mod session;
+mod login_state;
+mod imap_guard;
+mod qr_login;
+mod cli;
+mod config;
 
 #[derive(Serialize, Deserialize, Default, Clone)]
 pub struct Config {
     steam_login: String,
+    #[serde(default)]
     steam_password: String,
+    #[serde(default = "default_login_mode")]
+    login_mode: String,
+    #[serde(default = "default_qr_login_timeout_secs")]
+    qr_login_timeout_secs: u64,
     stats_url: String,
+    #[serde(default = "default_session_probe_url")]
+    session_probe_url: String,
+    #[serde(default = "default_session_probe_interval_secs")]
+    session_probe_interval_secs: u64,
+    #[serde(default = "default_session_expiry_warning_secs")]
+    session_expiry_warning_secs: u64,
     webhook_url: String,
     cookies_path: String,
+    #[serde(default)]
+    cookies_key: String,
+    #[serde(default)]
+    cookies_key_path: String,
     bot_token: String,
     owner_id: u64,
     role_id: u64,
@@ -35,29 +74,215 @@ pub struct Config {
     updates_channel_id: u64,
     #[serde(default)]
     updates_interval_secs: u64,
+    #[serde(default)]
+    data_dir: String,
+    #[serde(default)]
+    app_id: u64,
+    #[serde(default = "default_store_url")]
+    store_url: String,
+    #[serde(default = "default_community_url")]
+    community_url: String,
+    #[serde(default)]
+    reviews_channel_id: u64,
+    #[serde(default)]
+    reviews_interval_secs: u64,
+    #[serde(default = "default_steam_guard_attempts")]
+    steam_guard_attempts: u32,
+    #[serde(default)]
+    imap_host: String,
+    #[serde(default = "default_imap_port")]
+    imap_port: u16,
+    #[serde(default = "default_true")]
+    imap_tls: bool,
+    #[serde(default)]
+    imap_user: String,
+    #[serde(default)]
+    imap_password: String,
+    #[serde(default = "default_imap_folder")]
+    imap_folder: String,
+    #[serde(default = "default_imap_timeout_secs")]
+    imap_timeout_secs: u64,
+    #[serde(default)]
+    key_report_url: String,
+    #[serde(default)]
+    watched_key_batches: Vec<String>,
+    #[serde(default = "default_stats_cache_ttl_secs")]
+    stats_cache_ttl_secs: u64,
+    #[serde(default)]
+    chrome_ws_url: String,
+    #[serde(default)]
+    chrome_path: String,
+    #[serde(default)]
+    chrome_user_data_dir: String,
+    #[serde(default = "default_browser_timeout_secs")]
+    browser_timeout_secs: u64,
+    #[serde(default = "default_login_timeout_secs")]
+    login_timeout_secs: u64,
+    #[serde(default = "default_auth_code_timeout_secs")]
+    auth_code_timeout_secs: u64,
+    #[serde(default)]
+    report_screenshot_page: String,
+    #[serde(default = "default_log_level")]
+    log_level: String,
+    #[serde(default)]
+    log_json: bool,
+    #[serde(default = "default_log_rotation")]
+    log_rotation: String,
+}
+
+fn default_store_url() -> String {
+    "https://store.steampowered.com".to_string()
+}
+
+fn default_community_url() -> String {
+    "https://steamcommunity.com".to_string()
+}
+
+fn default_steam_guard_attempts() -> u32 {
+    3
+}
+
+fn default_login_mode() -> String {
+    "password".to_string()
+}
+
+fn default_qr_login_timeout_secs() -> u64 {
+    5 * 60
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_imap_port() -> u16 {
+    993
+}
+
+fn default_imap_folder() -> String {
+    "INBOX".to_string()
+}
+
+fn default_imap_timeout_secs() -> u64 {
+    120
+}
+
+fn default_session_probe_url() -> String {
+    "https://partner.steampowered.com/".to_string()
+}
+
+fn default_session_probe_interval_secs() -> u64 {
+    15 * 60
+}
+
+fn default_session_expiry_warning_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_stats_cache_ttl_secs() -> u64 {
+    60
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_rotation() -> String {
+    "daily".to_string()
+}
+
+fn default_browser_timeout_secs() -> u64 {
+    20
+}
+
+fn default_login_timeout_secs() -> u64 {
+    30
+}
+
+fn default_auth_code_timeout_secs() -> u64 {
+    15
+}
+
+impl Config {
+    pub fn data_path(&self, file: &str) -> PathBuf {
+        Path::new(&self.data_dir).join(file)
+    }
+}
+
+fn exit_with_errors(errors: &[String]) -> ! {
+    for error in errors {
+        eprintln!("config error: {}", error);
+    }
+    std::process::exit(1);
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cfg: Config = envy::from_env::<Config>()
-        .or_else(|_| toml::from_str::<Config>(&fs::read_to_string("config.toml").expect("config.toml not found")))
-        .expect("failed to parse config from env/config.toml");
-
-    let scrapper = Arc::new(RwLock::new(Scrapper::new(cfg.clone())?));
+    let args = CliArgs::parse(std::env::args().skip(1))?;
 
-    let mut bot = Bot::new(cfg.clone(), scrapper.clone()).await;
+    if args.command.as_deref() == Some("setup") {
+        return cli::setup(&args.config_path).await;
+    }
 
-    let res = {
-        let mut scrapper = scrapper.write().await;
-        scrapper.login().await
+    let source = ConfigSource { path: args.config_path.clone(), overrides: args.overrides.clone() };
+    let cfg = match source.load() {
+        Ok(cfg) => cfg,
+        Err(why) => exit_with_errors(&[why.to_string()]),
     };
 
+    if args.check_config {
+        return cli::check_config(&cfg).await;
+    }
+
+    let errors = cfg.validate();
+    if !errors.is_empty() {
+        exit_with_errors(&errors);
+    }
+
+    let _log_guard = logging::init(&cfg)?;
+
+    match args.command.as_deref() {
+        Some("login") => return cli::login(cfg).await,
+        Some("scrape") => return cli::scrape(cfg, args.json).await,
+        Some(other) => {
+            eprintln!("unknown command {}, expected one of: setup, login, scrape [--json]", other);
+            std::process::exit(2);
+        },
+        None => {},
+    }
+
+    let errors = cfg.validate_discord().await;
+    if !errors.is_empty() {
+        exit_with_errors(&errors);
+    }
+    if cfg.updates_channel_id == 0 {
+        info!("updates are disabled because `updates_channel_id` is 0");
+    }
+
+    let scrapper = ScrapperHandle::spawn(Scrapper::new(cfg.clone())?);
+    let players = Arc::new(RwLock::new(PlayerTracker::load(cfg.data_path("players.json"))?));
+
+    let mut bot = Bot::new(cfg.clone(), source, scrapper.clone(), players).await;
+
+    let res = scrapper.login("startup").await;
+    if let Err(why) = &res {
+        warn!("startup login failed: {:?}", why);
+    }
+    // nobody is around to answer a Steam Guard / QR prompt at startup, leave that to !login
+    if let Ok(LoginResult::AuthCodeNeeded | LoginResult::QrApprovalNeeded(_) | LoginResult::Captcha(_)) = res {
+        scrapper.cancel_login().await?;
+    }
+
     if res.map_or(false, |x| x == LoginResult::Success) {
-        start_interval(cfg.clone(), scrapper.clone(), bot.client.cache_and_http.http.clone(), bot.client.data.clone());
+        let handle = start_interval(cfg.clone(), scrapper.clone(), bot.client.cache_and_http.http.clone(), bot.client.data.clone());
+        bot.client.data.write().await.insert::<IntervalHandle>(handle.map(Arc::new));
     } else {
-        println!("cannot start interval: not logged in");
+        warn!("cannot start interval: not logged in");
     }
 
+    let monitor = session::start_session_monitor(cfg.clone(), scrapper.clone(), bot.client.cache_and_http.http.clone());
+    bot.client.data.write().await.insert::<SessionMonitorHandle>(monitor.map(Arc::new));
+    reviews::start_reviews_interval(cfg.clone(), bot.client.cache_and_http.http.clone())?;
+
     bot.run().await?;
 
     Ok(())
@@ -0,0 +1,58 @@
+use std::fmt;
+use std::fmt::Debug;
+
+use serde::Serialize;
+
+use crate::scrape_error::ScrapeError;
+use crate::scrapper::{Percent, Stats};
+use crate::utils::ParseMoney;
+
+#[derive(Default, PartialEq, PartialOrd, Clone, Serialize)]
+pub struct Ratio(pub f32);
+
+impl Debug for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}", self.0)
+    }
+}
+
+/// Ratios computed from the scraped `Stats`. `refund_rate_today` covers the period since the
+/// first scrape of the current UTC day.
+#[derive(Debug, Default, PartialEq, PartialOrd, Clone, Serialize)]
+pub struct DerivedMetrics {
+    pub net_revenue_per_steam_unit: Ratio,
+    pub net_to_gross: Percent,
+    pub wishlists_per_sale: Ratio,
+    pub retention: Percent,
+    pub refund_rate_today: Percent,
+}
+
+fn ratio(a: f32, b: f32) -> f32 {
+    if b == 0.0 {
+        return 0.0;
+    }
+    a / b
+}
+
+fn money(field: &str, raw: &str) -> Result<f32, ScrapeError> {
+    raw.parse_money().map_err(|_| ScrapeError::Parse { field: field.to_string(), raw: raw.to_string() })
+}
+
+impl DerivedMetrics {
+    pub fn compute(stats: &Stats, period_start: &Stats) -> Result<Self, ScrapeError> {
+        let gross = money("gross revenue", &stats.gross_revenue)?;
+        let net = money("net revenue", &stats.net_revenue)?;
+
+        Ok(DerivedMetrics {
+            net_revenue_per_steam_unit: Ratio(ratio(net, stats.steam_units as f32)),
+            net_to_gross: Percent(ratio(net, gross)),
+            wishlists_per_sale: Ratio(ratio(stats.wishlist_count as f32, stats.steam_units as f32)),
+            retention: Percent(ratio(stats.daily_active_users as f32, stats.lifetime_unique_users as f32)),
+            // returns are reported as negative numbers
+            refund_rate_today: Percent(ratio(
+                -(stats.units_returned - period_start.units_returned) as f32,
+                (stats.steam_units - period_start.steam_units) as f32,
+            )),
+        })
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+pub struct DayAggregate {
+    pub peak: i32,
+    sum: i64,
+    samples: u32,
+}
+
+impl DayAggregate {
+    pub fn average(&self) -> f32 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.sum as f32 / self.samples as f32
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct Peak {
+    pub players: i32,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+struct HeatmapCell {
+    sum: i64,
+    samples: u32,
+}
+
+/// Rolling aggregates of `Stats::current_players`, fed from every poll and persisted to disk.
+#[derive(Serialize, Deserialize, Default)]
+pub struct PlayerTracker {
+    #[serde(skip)]
+    path: PathBuf,
+    days: BTreeMap<NaiveDate, DayAggregate>,
+    all_time_peak: Option<Peak>,
+    heatmap: [[HeatmapCell; 24]; 7],
+}
+
+impl PlayerTracker {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut tracker = match fs::read_to_string(&path) {
+            Ok(str) => serde_json::from_str::<PlayerTracker>(&str)?,
+            Err(why) if why.kind() == ErrorKind::NotFound => PlayerTracker::default(),
+            Err(why) => return Err(anyhow!(why)),
+        };
+        tracker.path = path;
+        Ok(tracker)
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// Records a single sample. Returns the new peak if the all-time peak was broken.
+    pub fn record(&mut self, players: i32, at: DateTime<Utc>) -> Result<Option<Peak>> {
+        let day = self.days.entry(at.naive_utc().date()).or_default();
+        day.peak = day.peak.max(players);
+        day.sum += players as i64;
+        day.samples += 1;
+
+        let cell = &mut self.heatmap[at.weekday().num_days_from_monday() as usize][at.hour() as usize];
+        cell.sum += players as i64;
+        cell.samples += 1;
+
+        let broken = match self.all_time_peak {
+            Some(peak) if players > peak.players => {
+                self.all_time_peak = Some(Peak { players, at });
+                self.all_time_peak
+            }
+            Some(_) => None,
+            None => {
+                self.all_time_peak = Some(Peak { players, at });
+                None
+            }
+        };
+
+        self.save()?;
+
+        Ok(broken)
+    }
+
+    pub fn day(&self, date: NaiveDate) -> Option<DayAggregate> {
+        self.days.get(&date).copied()
+    }
+
+    pub fn all_time_peak(&self) -> Option<Peak> {
+        self.all_time_peak
+    }
+
+    pub fn summary(&self, date: NaiveDate) -> String {
+        let mut res = String::new();
+
+        match self.day(date) {
+            Some(day) => {
+                writeln!(res, "Peak: {}", day.peak).unwrap();
+                writeln!(res, "Average: {:.1}", day.average()).unwrap();
+            }
+            None => writeln!(res, "No samples for {}", date).unwrap(),
+        }
+
+        if let Some(peak) = self.all_time_peak {
+            writeln!(res, "All-time peak: {} ({})", peak.players, peak.at.format("%Y-%m-%d %H:%M UTC")).unwrap();
+        }
+
+        res
+    }
+
+    /// Average players per hour of day (columns, UTC) and day of week (rows).
+    pub fn heatmap(&self) -> String {
+        let mut res = String::from("    ");
+        for hour in 0..24 {
+            write!(res, "{:>4}", hour).unwrap();
+        }
+        res.push('\n');
+
+        for (day, row) in self.heatmap.iter().enumerate() {
+            res.push_str(WEEKDAYS[day]);
+            res.push(' ');
+            for cell in row {
+                if cell.samples == 0 {
+                    res.push_str("   -");
+                } else {
+                    write!(res, "{:>4.0}", cell.sum as f32 / cell.samples as f32).unwrap();
+                }
+            }
+            res.push('\n');
+        }
+
+        res
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use aes_gcm::aead::OsRng;
+use aes_gcm::aead::rand_core::RngCore;
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, ImageOutputFormat, Luma};
+use qrcode::QrCode;
+use serde::Deserialize;
+
+const API_URL: &str = "https://api.steampowered.com/IAuthenticationService";
+const FINALIZE_URL: &str = "https://login.steampowered.com/jwt/finalizelogin";
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    response: T,
+}
+
+#[derive(Deserialize)]
+struct BeginResponse {
+    client_id: String,
+    request_id: String,
+    challenge_url: String,
+    #[serde(default)]
+    interval: f32,
+}
+
+#[derive(Deserialize)]
+struct PollResponse {
+    new_client_id: Option<String>,
+    new_challenge_url: Option<String>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FinalizeResponse {
+    #[serde(rename = "steamID")]
+    steam_id: String,
+    transfer_info: Vec<TransferInfo>,
+}
+
+#[derive(Deserialize)]
+struct TransferInfo {
+    url: String,
+    params: HashMap<String, String>,
+}
+
+/// A pending QR auth session. `challenge_url` is what gets encoded in the QR code; Steam may
+/// rotate it while the session is pending.
+#[derive(Clone, PartialEq)]
+pub struct QrChallenge {
+    client_id: String,
+    request_id: String,
+    pub challenge_url: String,
+    pub interval: Duration,
+}
+
+impl QrChallenge {
+    pub fn render_png(&self) -> Result<Vec<u8>> {
+        let img = QrCode::new(self.challenge_url.as_bytes())?
+            .render::<Luma<u8>>()
+            .min_dimensions(300, 300)
+            .build();
+
+        let mut png = vec![];
+        DynamicImage::ImageLuma8(img).write_to(&mut png, ImageOutputFormat::Png)?;
+        Ok(png)
+    }
+}
+
+pub async fn begin(client: &reqwest::Client) -> Result<QrChallenge> {
+    let res = client.post(format!("{}/BeginAuthSessionViaQR/v1/", API_URL))
+        .form(&[("device_friendly_name", "Decorporation bot"), ("platform_type", "2")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ApiResponse<BeginResponse>>()
+        .await?
+        .response;
+
+    Ok(QrChallenge {
+        client_id: res.client_id,
+        request_id: res.request_id,
+        challenge_url: res.challenge_url,
+        interval: Duration::from_secs_f32(res.interval.max(1.0)),
+    })
+}
+
+/// Polls the auth session once. Returns the refresh token once the login was approved in the
+/// mobile app.
+pub async fn poll(client: &reqwest::Client, challenge: &mut QrChallenge) -> Result<Option<String>> {
+    let res = client.post(format!("{}/PollAuthSessionStatus/v1/", API_URL))
+        .form(&[("client_id", challenge.client_id.as_str()), ("request_id", challenge.request_id.as_str())])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ApiResponse<PollResponse>>()
+        .await?
+        .response;
+
+    if let Some(client_id) = res.new_client_id {
+        challenge.client_id = client_id;
+    }
+    if let Some(url) = res.new_challenge_url {
+        challenge.challenge_url = url;
+    }
+
+    Ok(res.refresh_token)
+}
+
+/// Exchanges the refresh token for web session cookies on every Steam domain. The cookies end up
+/// in the client's cookie store.
+pub async fn finalize(client: &reqwest::Client, refresh_token: &str) -> Result<()> {
+    // the session id is a CSRF token, so it mustn't be guessable
+    let mut bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut bytes);
+    let session_id = bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+    let res = client.post(FINALIZE_URL)
+        .form(&[("nonce", refresh_token), ("sessionid", session_id.as_str()), ("redir", "https://steamcommunity.com/login/home/?goto=")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<FinalizeResponse>()
+        .await?;
+
+    if res.transfer_info.is_empty() {
+        return Err(anyhow!("finalizelogin returned no transfer targets"));
+    }
+
+    for transfer in res.transfer_info {
+        let mut form = transfer.params;
+        form.insert("steamID".to_string(), res.steam_id.clone());
+
+        client.post(&transfer.url)
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use regex::Regex;
+
+use crate::Config;
+
+const REDACTED: &str = "<redacted>";
+
+/// Scrubs secrets out of text before it's posted to Discord. Covers the configured secrets
+/// verbatim plus patterns for values that only show up at runtime, like cookies and tokens.
+#[derive(Clone)]
+pub struct Redactor {
+    secrets: Vec<String>,
+    patterns: Vec<(Regex, String)>,
+}
+
+impl Redactor {
+    pub fn from_config(cfg: &Config) -> Self {
+        let mut secrets = vec![
+            cfg.steam_password.clone(),
+            cfg.bot_token.clone(),
+            cfg.webhook_url.clone(),
+            cfg.cookies_key.clone(),
+            cfg.imap_password.clone(),
+        ];
+        // partner URLs can carry tokens in their query string
+        for url in [&cfg.stats_url, &cfg.key_report_url, &cfg.session_probe_url] {
+            if let Some((_, query)) = url.split_once('?') {
+                secrets.push(query.to_string());
+            }
+        }
+        // very short values would mangle unrelated text, and longer ones must go first so a
+        // secret containing another isn't left half replaced
+        secrets.retain(|s| s.len() >= 4);
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+
+        let patterns = [
+            (r"(?i)\b(steamLoginSecure|steamRememberLogin|steamMachineAuth\d*|steamRefresh_steam|sessionid|browserid)=[^;&\s]+", format!("$1={}", REDACTED)),
+            (r#""value"\s*:\s*"[^"]*""#, format!(r#""value":"{}""#, REDACTED)),
+            (r"(?i)([?&](?:key|api_key|apikey|access_token|token|auth)=)[^&\s]+", format!("${{1}}{}", REDACTED)),
+            (r"https://(?:\w+\.)?discord(?:app)?\.com/api/webhooks/\S+", REDACTED.to_string()),
+            (r"eyJ[\w-]+\.[\w-]+\.[\w-]+", REDACTED.to_string()),
+        ].into_iter().map(|(re, replacement)| (Regex::new(re).unwrap(), replacement)).collect();
+
+        Redactor { secrets, patterns }
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+        for (re, replacement) in &self.patterns {
+            text = re.replace_all(&text, replacement.as_str()).into_owned();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor(cfg: Config) -> Redactor {
+        Redactor::from_config(&cfg)
+    }
+
+    #[test]
+    fn redacts_cookie_header_values() {
+        let text = "Cookie: steamLoginSecure=76561198000000000%7C%7CeyJhbGc; sessionid=0123456789abcdef";
+
+        assert_eq!(
+            redactor(Config::default()).redact(text),
+            "Cookie: steamLoginSecure=<redacted>; sessionid=<redacted>",
+        );
+    }
+
+    #[test]
+    fn redacts_stats_url_query_string() {
+        let cfg = Config {
+            stats_url: "https://partner.steampowered.com/app/details/123/?token=s3cr3tvalue&range=day".to_string(),
+            ..Config::default()
+        };
+        let text = format!("failed to fetch {}: timed out", cfg.stats_url);
+
+        assert_eq!(
+            redactor(cfg).redact(&text),
+            "failed to fetch https://partner.steampowered.com/app/details/123/?<redacted>: timed out",
+        );
+    }
+
+    #[test]
+    fn redacts_webhook_urls() {
+        let cfg = Config {
+            webhook_url: "https://discord.com/api/webhooks/111/configured-token".to_string(),
+            ..Config::default()
+        };
+        let redactor = redactor(cfg);
+
+        assert_eq!(redactor.redact("posting to https://discord.com/api/webhooks/111/configured-token"), "posting to <redacted>");
+        assert_eq!(redactor.redact("posting to https://canary.discordapp.com/api/webhooks/222/other-token"), "posting to <redacted>");
+    }
+
+    #[test]
+    fn redacts_longer_secret_before_one_it_contains() {
+        let cfg = Config {
+            steam_password: "hunter2hunter2".to_string(),
+            imap_password: "hunter2".to_string(),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            redactor(cfg).redact("steam hunter2hunter2, imap hunter2"),
+            "steam <redacted>, imap <redacted>",
+        );
+    }
+
+    #[test]
+    fn leaves_short_secrets_alone() {
+        let cfg = Config { imap_password: "abc".to_string(), ..Config::default() };
+
+        assert_eq!(redactor(cfg).redact("abcdef"), "abcdef");
+    }
+}
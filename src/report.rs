@@ -0,0 +1,18 @@
+use chrono::NaiveDate;
+
+use crate::players::PlayerTracker;
+use crate::scrapper::Stats;
+
+/// The report for `date`, split into messages so each stays under Discord's length limit.
+/// `stats` should be the last stats seen on that day, `None` if there were none.
+pub fn daily_report(date: NaiveDate, stats: Option<&Stats>, players: &PlayerTracker) -> Vec<String> {
+    let stats = match stats {
+        Some(stats) => stats.describe(),
+        None => format!("No stats were scraped on {}", date),
+    };
+
+    vec![
+        format!("**Daily report for {}**\n{}", date, stats),
+        format!("**Players**\n```{}```\n**Average players by hour (UTC)**\n```{}```", players.summary(date), players.heatmap()),
+    ]
+}
@@ -0,0 +1,300 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::http::Http;
+use serenity::model::id::ChannelId;
+use tokio::{task, time};
+use tracing::{error, warn};
+
+use crate::Config;
+
+#[derive(Deserialize)]
+struct ReviewsResponse {
+    success: i32,
+    query_summary: QuerySummary,
+    #[serde(default)]
+    reviews: Vec<Review>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct QuerySummary {
+    #[serde(default)]
+    pub review_score: i32,
+    #[serde(default)]
+    pub review_score_desc: String,
+    #[serde(default)]
+    pub total_positive: i32,
+    #[serde(default)]
+    pub total_negative: i32,
+    #[serde(default)]
+    pub total_reviews: i32,
+}
+
+#[derive(Deserialize)]
+struct Review {
+    recommendationid: String,
+    author: Author,
+    language: String,
+    review: String,
+    timestamp_created: i64,
+    voted_up: bool,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    steamid: String,
+    #[serde(default)]
+    playtime_at_review: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReviewSnapshot {
+    at: DateTime<Utc>,
+    #[serde(flatten)]
+    summary: QuerySummary,
+}
+
+/// Review score history and the newest review already posted, persisted to disk.
+#[derive(Serialize, Deserialize, Default)]
+pub struct ReviewTracker {
+    #[serde(skip)]
+    path: PathBuf,
+    last_timestamp: Option<i64>,
+    history: Vec<ReviewSnapshot>,
+}
+
+impl ReviewTracker {
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut tracker = match fs::read_to_string(&path) {
+            Ok(str) => serde_json::from_str::<ReviewTracker>(&str)?,
+            Err(why) if why.kind() == ErrorKind::NotFound => ReviewTracker::default(),
+            Err(why) => return Err(anyhow!(why)),
+        };
+        tracker.path = path;
+        Ok(tracker)
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::write(&self.path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    fn record_summary(&mut self, summary: &QuerySummary) {
+        if self.history.last().map_or(true, |x| &x.summary != summary) {
+            self.history.push(ReviewSnapshot { at: Utc::now(), summary: summary.clone() });
+        }
+    }
+
+    /// Returns reviews newer than the last seen one, oldest first. On the first run nothing
+    /// is returned so the whole backlog doesn't get posted at once.
+    fn take_new(&mut self, mut reviews: Vec<Review>) -> Vec<Review> {
+        reviews.sort_by_key(|r| r.timestamp_created);
+
+        let newest = reviews.last().map(|r| r.timestamp_created);
+        let res = match self.last_timestamp {
+            Some(last) => reviews.into_iter().filter(|r| r.timestamp_created > last).collect(),
+            None => vec![],
+        };
+
+        if newest > self.last_timestamp {
+            self.last_timestamp = newest;
+        }
+
+        res
+    }
+}
+
+async fn fetch_reviews(client: &reqwest::Client, cfg: &Config) -> Result<ReviewsResponse> {
+    let url = format!(
+        "{}/appreviews/{}?json=1&filter=recent&language=all&purchase_type=all&num_per_page=20",
+        cfg.store_url, cfg.app_id
+    );
+
+    let res = client.get(&url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ReviewsResponse>()
+        .await?;
+
+    if res.success != 1 {
+        return Err(anyhow!("appreviews returned success={}", res.success));
+    }
+
+    Ok(res)
+}
+
+async fn post_review(http: &Http, ch_id: ChannelId, app_id: u64, review: &Review) -> Result<()> {
+    let link = format!("https://steamcommunity.com/profiles/{}/recommended/{}/", review.author.steamid, app_id);
+    let text = if review.review.chars().count() > 1000 {
+        format!("{}...", review.review.chars().take(1000).collect::<String>())
+    } else {
+        review.review.clone()
+    };
+    let created = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(review.timestamp_created, 0), Utc);
+
+    ch_id.send_message(http, |m| m.embed(|e| {
+        e.title(if review.voted_up { "👍 Recommended" } else { "👎 Not Recommended" })
+            .url(&link)
+            .description(text)
+            .colour(if review.voted_up { 0x66c0f4 } else { 0xa34c25 })
+            .field("Language", &review.language, true)
+            .field("Playtime at review", format!("{:.1} h", review.author.playtime_at_review as f32 / 60.0), true)
+            .timestamp(created.to_rfc3339())
+    })).await?;
+
+    Ok(())
+}
+
+pub fn start_reviews_interval(cfg: Config, http: Arc<Http>) -> Result<()> {
+    let ch_id = ChannelId(cfg.reviews_channel_id);
+
+    if ch_id == 0 || cfg.app_id == 0 {
+        return Ok(());
+    }
+
+    let mut tracker = ReviewTracker::load(cfg.data_path("reviews.json"))?;
+    let mut interval = time::interval(Duration::from_secs(cfg.reviews_interval_secs.max(60)));
+    let client = reqwest::Client::new();
+
+    task::spawn(async move {
+        loop {
+            interval.tick().await;
+
+            let res = match fetch_reviews(&client, &cfg).await {
+                Ok(res) => res,
+                Err(why) => {
+                    warn!("failed to fetch reviews: {:?}", why);
+                    continue;
+                }
+            };
+
+            tracker.record_summary(&res.query_summary);
+
+            for review in tracker.take_new(res.reviews) {
+                if let Err(why) = post_review(&http, ch_id, cfg.app_id, &review).await {
+                    warn!("failed to post review {}: {:?}", review.recommendationid, why);
+                }
+            }
+
+            if let Err(why) = tracker.save() {
+                error!("failed to save reviews: {:?}", why);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/appreviews.json");
+
+    /// Answers every request with `body` and reports each request line.
+    fn serve(body: &'static str) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let _ = tx.send(request.lines().next().unwrap_or_default().to_string());
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body,
+                ).unwrap();
+            }
+        });
+
+        (url, rx)
+    }
+
+    fn config(store_url: String) -> Config {
+        Config { store_url, app_id: 1234, ..Config::default() }
+    }
+
+    #[tokio::test]
+    async fn fetches_reviews_for_the_app() {
+        let (url, requests) = serve(FIXTURE);
+
+        let res = fetch_reviews(&reqwest::Client::new(), &config(url)).await.unwrap();
+
+        assert!(requests.recv().unwrap().starts_with("GET /appreviews/1234?json=1&filter=recent"));
+        assert_eq!(res.query_summary.total_reviews, 50);
+        assert_eq!(res.query_summary.review_score_desc, "Mostly Positive");
+        assert_eq!(res.reviews.len(), 3);
+        assert_eq!(res.reviews[2].author.playtime_at_review, 0);
+    }
+
+    #[tokio::test]
+    async fn take_new_skips_backlog_and_seen_reviews() {
+        let (url, _requests) = serve(FIXTURE);
+        let cfg = config(url);
+        let client = reqwest::Client::new();
+        let mut tracker = ReviewTracker::default();
+
+        // the first run only remembers where it is
+        let res = fetch_reviews(&client, &cfg).await.unwrap();
+        assert!(tracker.take_new(res.reviews).is_empty());
+        assert_eq!(tracker.last_timestamp, Some(1650000300));
+
+        let res = fetch_reviews(&client, &cfg).await.unwrap();
+        assert!(tracker.take_new(res.reviews).is_empty());
+    }
+
+    #[tokio::test]
+    async fn take_new_returns_newer_reviews_oldest_first() {
+        let (url, _requests) = serve(FIXTURE);
+        let mut tracker = ReviewTracker::default();
+        tracker.last_timestamp = Some(1650000100);
+
+        let res = fetch_reviews(&reqwest::Client::new(), &config(url)).await.unwrap();
+        let ids = tracker.take_new(res.reviews).into_iter().map(|r| r.recommendationid).collect::<Vec<_>>();
+
+        assert_eq!(ids, ["200", "300"]);
+        assert_eq!(tracker.last_timestamp, Some(1650000300));
+    }
+
+    #[tokio::test]
+    async fn record_summary_only_keeps_changes() {
+        let (url, _requests) = serve(FIXTURE);
+        let mut tracker = ReviewTracker::default();
+
+        let res = fetch_reviews(&reqwest::Client::new(), &config(url)).await.unwrap();
+        tracker.record_summary(&res.query_summary);
+        tracker.record_summary(&res.query_summary);
+        assert_eq!(tracker.history.len(), 1);
+
+        let changed = QuerySummary { total_positive: 42, total_reviews: 51, ..res.query_summary };
+        tracker.record_summary(&changed);
+        assert_eq!(tracker.history.len(), 2);
+        assert_eq!(tracker.history.last().unwrap().summary.total_reviews, 51);
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+/// What went wrong while logging in or scraping, so callers can tell the user what to do about
+/// it instead of dumping the error chain.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScrapeError {
+    NotLoggedIn,
+    SessionExpired,
+    /// An element we scrape is gone, Steam probably changed the page.
+    LayoutChanged { field: String },
+    Network(String),
+    RateLimited,
+    /// Logging in again needs a Steam Guard code, captcha or QR approval from someone.
+    SteamGuardRequired,
+    BrowserUnavailable(String),
+    Parse { field: String, raw: String },
+    /// Someone is in the middle of logging in, the scrapper takes no other work until they finish.
+    LoginInProgress,
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScrapeError::NotLoggedIn => write!(f, "not logged in to Steam"),
+            ScrapeError::SessionExpired => write!(f, "the Steam session expired"),
+            ScrapeError::LayoutChanged { field } => write!(f, "couldn't find {} on the partner page, its layout probably changed", field),
+            ScrapeError::Network(why) => write!(f, "couldn't reach Steam: {}", why),
+            ScrapeError::RateLimited => write!(f, "Steam is rate limiting us"),
+            ScrapeError::SteamGuardRequired => write!(f, "logging in again needs a Steam Guard code, captcha or QR approval"),
+            ScrapeError::BrowserUnavailable(why) => write!(f, "the browser isn't available: {}", why),
+            ScrapeError::Parse { field, raw } => write!(f, "couldn't read {} from \"{}\"", field, raw),
+            ScrapeError::LoginInProgress => write!(f, "a login is in progress"),
+        }
+    }
+}
+
+impl std::error::Error for ScrapeError {}
+
+impl From<reqwest::Error> for ScrapeError {
+    fn from(why: reqwest::Error) -> Self {
+        ScrapeError::Network(why.to_string())
+    }
+}
+
+impl ScrapeError {
+    /// The `ScrapeError` anywhere in an error's chain.
+    pub fn find(why: &anyhow::Error) -> Option<&ScrapeError> {
+        why.chain().find_map(|e| e.downcast_ref::<ScrapeError>())
+    }
+
+    /// Worth retrying on the next tick without bothering anyone.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ScrapeError::Network(_) | ScrapeError::RateLimited | ScrapeError::LoginInProgress)
+    }
+
+    pub fn needs_login(&self) -> bool {
+        matches!(self, ScrapeError::NotLoggedIn | ScrapeError::SessionExpired | ScrapeError::SteamGuardRequired)
+    }
+
+    /// What to do about it, phrased for the Discord reply.
+    pub fn recovery(&self) -> &'static str {
+        match self {
+            ScrapeError::NotLoggedIn | ScrapeError::SessionExpired => "Log in again with `login`.",
+            ScrapeError::SteamGuardRequired => "Run `login` and answer the prompt it sends.",
+            ScrapeError::LayoutChanged { .. } | ScrapeError::Parse { .. } => "The scrapper needs updating for the new page, ping the owner.",
+            ScrapeError::Network(_) => "Try again in a bit.",
+            ScrapeError::RateLimited => "Wait a while before trying again.",
+            ScrapeError::BrowserUnavailable(_) => "Check that Chrome is installed or `chrome_ws_url` points at a running one.",
+            ScrapeError::LoginInProgress => "Try again once it's finished.",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+
+    #[test]
+    fn find_looks_through_context() {
+        let why = Err::<(), _>(ScrapeError::SessionExpired).context("fetching stats").unwrap_err();
+
+        assert_eq!(ScrapeError::find(&why), Some(&ScrapeError::SessionExpired));
+    }
+
+    #[test]
+    fn find_returns_none_for_other_errors() {
+        assert_eq!(ScrapeError::find(&anyhow!("boom")), None);
+    }
+
+    #[test]
+    fn only_network_rate_limits_and_running_logins_are_transient() {
+        assert!(ScrapeError::Network("timed out".to_string()).is_transient());
+        assert!(ScrapeError::RateLimited.is_transient());
+        assert!(ScrapeError::LoginInProgress.is_transient());
+        assert!(!ScrapeError::SessionExpired.is_transient());
+        assert!(!ScrapeError::LayoutChanged { field: "net revenue".to_string() }.is_transient());
+    }
+
+    #[test]
+    fn session_problems_need_login() {
+        assert!(ScrapeError::NotLoggedIn.needs_login());
+        assert!(ScrapeError::SessionExpired.needs_login());
+        assert!(ScrapeError::SteamGuardRequired.needs_login());
+        assert!(!ScrapeError::RateLimited.needs_login());
+        assert!(!ScrapeError::BrowserUnavailable("no chrome".to_string()).needs_login());
+    }
+}
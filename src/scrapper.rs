@@ -1,39 +1,70 @@
 use std::{fmt, fs, io};
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
-use std::fs::File;
-use std::io::{ErrorKind, Write};
+use std::io::ErrorKind;
 use std::path::Path;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use anyhow::{anyhow, Result};
-use headless_chrome::{Browser, LaunchOptions, Tab};
-use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam, DeleteCookies};
-use headless_chrome::protocol::cdp::Page::{CaptureScreenshotFormatOption, DeleteCookie};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use scraper::{Html, Selector};
-use tokio::time;
+use serde::Serialize;
+use tracing::{info, info_span, warn, Instrument};
 use crate::utils::*;
 
 use crate::Config;
+use crate::browser::{AuthCodePage, BrowserSettings, BrowserWorker, LoginPage, PageCapture};
+use crate::cookie_store::{CookieCipher, PersistentCookieStore};
+use crate::imap_guard::ImapSettings;
+use crate::keys::parse_key_report;
+use crate::login_state::{LoginState, ScrapperStatus};
+use crate::metrics::DerivedMetrics;
+use crate::qr_login::{self, QrChallenge};
+use crate::scrape_error::ScrapeError;
+use crate::store_page::{get_store_page, StorePage};
+
+const PARTNER_URL: &str = "https://partner.steampowered.com";
+
+/// Partner site pages `!screenshot` can take.
+pub const SCREENSHOT_PAGES: &[&str] = &["app", "regional", "wishlist"];
 
 pub struct Scrapper {
     login_url: String,
     stats_url: String,
+    session_probe_url: String,
+    key_report_url: String,
     steam_username: String,
     steam_password: String,
-    cookies_path: String,
-    browser: Option<Arc<Browser>>,
-    tab: Option<Arc<Tab>>,
-    is_logged_in: bool,
+    qr_login: bool,
+    imap: Option<ImapSettings>,
+    cookie_store: Arc<PersistentCookieStore>,
+    app_id: u64,
+    store_url: String,
+    community_url: String,
+    last_store_page: Option<StorePage>,
+    last_key_batches: BTreeMap<String, i32>,
+    day_start: Option<(NaiveDate, Stats)>,
+    browser: BrowserWorker,
+    status: Arc<Mutex<ScrapperStatus>>,
+    last_authenticated_fetch: Option<DateTime<Utc>>,
     client: Option<Arc<reqwest::Client>>,
 }
 
-unsafe impl Send for Scrapper {}
-unsafe impl Sync for Scrapper {}
+/// Reads one row of the lifetime summary table on the partner page.
+fn summary_text(document: &Html, field: &str, row: u32) -> Result<String, ScrapeError> {
+    document.get_element_text(&format!(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child({}) > td:nth-child(2)", row))
+        .map_err(|_| ScrapeError::LayoutChanged { field: field.to_string() })
+}
 
-#[derive(Default, PartialEq, PartialOrd, Clone)]
-struct Percent(f32);
+fn summary_int(document: &Html, field: &str, row: u32) -> Result<i32, ScrapeError> {
+    let raw = summary_text(document, field, row)?;
+    raw.trim().to_string().atoi().map_err(|_| ScrapeError::Parse { field: field.to_string(), raw })
+}
+
+#[derive(Default, PartialEq, PartialOrd, Clone, Serialize)]
+pub struct Percent(pub f32);
 
 impl Debug for Percent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -41,128 +72,329 @@ impl Debug for Percent {
     }
 }
 
-#[derive(Debug, Default, PartialEq, PartialOrd, Clone)]
+#[derive(Default, PartialEq, PartialOrd, Clone, Serialize)]
 pub struct Stats {
-    total_units: i32,
-    steam_units: i32,
-    units_returned: i32,
-    return_percent: Percent,
-    gross_revenue: String,
-    net_revenue: String,
-    current_players: i32,
-    daily_active_users: i32,
-    lifetime_unique_users: i32,
-    wishlist_count: i32,
+    pub total_units: i32,
+    pub steam_units: i32,
+    pub key_units: i32,
+    pub units_returned: i32,
+    pub return_percent: Percent,
+    pub gross_revenue: String,
+    pub net_revenue: String,
+    pub current_players: i32,
+    pub daily_active_users: i32,
+    pub lifetime_unique_users: i32,
+    pub wishlist_count: i32,
+    pub store: StorePage,
+    pub key_batches: BTreeMap<String, i32>,
+    pub derived: DerivedMetrics,
+}
+
+/// Only the scraped values, `describe` adds the derived metrics as their own section. Key batches
+/// are summed up, there can be too many of them to list in one Discord message.
+impl Debug for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stats")
+            .field("total_units", &self.total_units)
+            .field("steam_units", &self.steam_units)
+            .field("key_units", &self.key_units)
+            .field("units_returned", &self.units_returned)
+            .field("return_percent", &self.return_percent)
+            .field("gross_revenue", &self.gross_revenue)
+            .field("net_revenue", &self.net_revenue)
+            .field("current_players", &self.current_players)
+            .field("daily_active_users", &self.daily_active_users)
+            .field("lifetime_unique_users", &self.lifetime_unique_users)
+            .field("wishlist_count", &self.wishlist_count)
+            .field("store", &self.store)
+            .field("key_batches", &format_args!("{} batches, {} activations", self.key_batches.len(), self.key_batches.values().sum::<i32>()))
+            .finish()
+    }
+}
+
+impl Stats {
+    pub fn describe(&self) -> String {
+        format!("```{:#?}```**Metrics**\n```{:#?}```", self, self.derived)
+    }
 }
 
 #[derive(PartialEq)]
 pub enum LoginResult {
     Success,
     AuthCodeNeeded,
+    QrApprovalNeeded(QrChallenge),
+    /// PNG of the captcha image, answer it with `provide_captcha`.
+    Captcha(Vec<u8>),
+    WrongPassword(PageCapture),
+    RateLimited(PageCapture),
+}
+
+impl LoginResult {
+    /// Why the login ended without a session, for the results that need someone to step in.
+    pub fn failure_reason(&self) -> Option<&'static str> {
+        match self {
+            LoginResult::Captcha(_) => Some("Steam asked for a captcha"),
+            LoginResult::WrongPassword(_) => Some("Steam rejected the login or password"),
+            LoginResult::RateLimited(_) => Some("Steam is rate limiting logins from this network, wait before trying again"),
+            _ => None,
+        }
+    }
+
+    /// Short name for the log.
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            LoginResult::Success => "success",
+            LoginResult::AuthCodeNeeded => "auth_code_needed",
+            LoginResult::QrApprovalNeeded(_) => "qr_approval_needed",
+            LoginResult::Captcha(_) => "captcha",
+            LoginResult::WrongPassword(_) => "wrong_password",
+            LoginResult::RateLimited(_) => "rate_limited",
+        }
+    }
+
+    /// The login page as it was when Steam turned the login down.
+    pub fn capture(&self) -> Option<&PageCapture> {
+        match self {
+            LoginResult::WrongPassword(capture) | LoginResult::RateLimited(capture) => Some(capture),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub enum AuthCodeResult {
+    Success,
+    WrongCode,
 }
 
 impl Scrapper {
     pub fn new(cfg: Config) -> Result<Self> {
         // let (browser, tab) = Self::open()?;
+        let cookie_store = PersistentCookieStore::load(cfg.cookies_path.clone(), CookieCipher::from_config(&cfg)?)?;
+        let imap = ImapSettings::from_config(&cfg);
+        let browser = BrowserWorker::spawn(BrowserSettings::from_config(&cfg))?;
         Ok(Scrapper {
             login_url: "https://partner.steampowered.com/login/".to_string(),
             stats_url: cfg.stats_url,
+            session_probe_url: cfg.session_probe_url,
+            key_report_url: cfg.key_report_url,
             steam_username: cfg.steam_login,
             steam_password: cfg.steam_password,
-            cookies_path: cfg.cookies_path,
-            browser: None,
-            tab: None,
-            is_logged_in: false,
+            qr_login: cfg.login_mode == "qr",
+            imap,
+            cookie_store: Arc::new(cookie_store),
+            app_id: cfg.app_id,
+            store_url: cfg.store_url,
+            community_url: cfg.community_url,
+            last_store_page: None,
+            last_key_batches: BTreeMap::new(),
+            day_start: None,
+            browser,
+            status: Arc::new(Mutex::new(ScrapperStatus::default())),
+            last_authenticated_fetch: None,
             client: None,
         })
     }
 
-    fn is_open(&self) -> bool {
-        match self.browser.clone() {
-            Some(b) => b.is_open(),
-            None => false
+    pub fn status(&self) -> Arc<Mutex<ScrapperStatus>> {
+        self.status.clone()
+    }
+
+    fn state(&self) -> LoginState {
+        self.status.lock().unwrap().state().clone()
+    }
+
+    fn transition(&self, to: LoginState) -> Result<()> {
+        self.status.lock().unwrap().transition(to)
+    }
+
+    async fn fail(&mut self, why: &anyhow::Error) {
+        if let Err(why) = self.browser.close().await {
+            warn!("failed to close browser: {:?}", why);
         }
+        self.transition(LoginState::Failed(why.to_string())).unwrap();
     }
 
-    fn open(&mut self) -> Result<()> {
-        let browser = Browser::new(
-            LaunchOptions::default_builder()
-                .headless(true)
-                .build()
-                .expect("Could not find chrome executable"))?;
+    /// `user` is who asked for the login, for the log: a Discord tag, or what started it.
+    pub async fn login(&mut self, user: &str) -> Result<LoginResult> {
+        let span = info_span!("login", user, mode = if self.qr_login { "qr" } else { "browser" });
+        let start = Instant::now();
+        let res = self.run_login().instrument(span.clone()).await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+
+        span.in_scope(|| match &res {
+            Ok(result) => info!(duration_ms, outcome = result.outcome(), "login finished"),
+            Err(why) => warn!(duration_ms, outcome = "error", "login failed: {:#}", why),
+        });
+        res
+    }
 
-        let tab = browser.new_tab()?;
+    async fn run_login(&mut self) -> Result<LoginResult> {
+        if self.state().is_login_in_progress() {
+            return Err(anyhow!("login already in progress"));
+        }
 
-        self.browser = Some(Arc::new(browser));
-        self.tab = Some(tab);
+        let started_at = Utc::now();
+        let res = self.try_login().await;
+        if let Err(why) = &res {
+            self.fail(why).await;
+        }
 
-        Ok(())
+        if let (Ok(LoginResult::AuthCodeNeeded), Some(imap)) = (&res, self.imap.clone()) {
+            match self.auth_code_from_email(&imap, started_at).await {
+                Ok(()) => return Ok(LoginResult::Success),
+                Err(why) if self.state() != LoginState::AwaitingSteamGuard => return Err(why),
+                Err(why) => warn!("could not get Steam Guard code from email: {:?}", why),
+            }
+        }
+
+        res
+    }
+
+    async fn auth_code_from_email(&mut self, imap: &ImapSettings, since: DateTime<Utc>) -> Result<()> {
+        let code = imap.wait_for_code(since).await?;
+        match self.provide_auth_code(code).await? {
+            AuthCodeResult::Success => Ok(()),
+            AuthCodeResult::WrongCode => Err(anyhow!("Steam rejected the code from the email")),
+        }
     }
 
-    pub async fn login(&mut self) -> Result<LoginResult> {
+    async fn try_login(&mut self) -> Result<LoginResult> {
         self.client = Some(Arc::new(self.get_client()?));
 
         if self.check_if_logged_in().await.is_ok() {
-            self.is_logged_in = true;
+            self.transition(LoginState::LoggedIn)?;
             return Ok(LoginResult::Success);
         }
 
-        if !self.is_open() {
-            self.open()?;
+        if self.state() == LoginState::LoggedIn {
+            self.transition(LoginState::Expired)?;
         }
 
-        if let Err(why) = self.load_cookies() {
-            println!("load cookies failed: {}", why);
+        if self.qr_login {
+            let challenge = qr_login::begin(self.client.as_ref().unwrap()).await?;
+            self.transition(LoginState::AwaitingQrApproval)?;
+            return Ok(LoginResult::QrApprovalNeeded(challenge));
         }
 
-        let tab = self.tab.clone().unwrap();
+        self.transition(LoginState::LaunchingBrowser)?;
 
-        tab.navigate_to(&self.login_url)?;
+        let page = self.browser.login(&self.login_url, &self.steam_username, &self.steam_password, self.cookie_store.to_cookie_params()).await?;
+        self.handle_login_page(page).await
+    }
 
-        let username_input = tab.wait_for_element("input#username");
-        if username_input.is_err() {
-            println!("already logged in");
-            self.is_logged_in = true;
-            return Ok(LoginResult::Success);
+    async fn handle_login_page(&mut self, page: LoginPage) -> Result<LoginResult> {
+        match page {
+            LoginPage::AuthCodeNeeded => {
+                self.transition(LoginState::AwaitingSteamGuard)?;
+                Ok(LoginResult::AuthCodeNeeded)
+            },
+            LoginPage::Captcha(image) => {
+                self.transition(LoginState::AwaitingCaptcha)?;
+                Ok(LoginResult::Captcha(image))
+            },
+            LoginPage::WrongPassword(capture) => self.login_rejected(LoginResult::WrongPassword(capture)).await,
+            LoginPage::RateLimited(capture) => self.login_rejected(LoginResult::RateLimited(capture)).await,
+            // the browser got in while the HTTP client didn't, so its cookies are the ones to keep
+            LoginPage::AlreadyLoggedIn(cookies) | LoginPage::LoggedIn(cookies) => {
+                self.cookie_store.replace_from_browser(&cookies)?;
+
+                self.transition(LoginState::LoggedIn)?;
+                self.client = Some(Arc::new(self.get_client()?));
+                self.browser.close().await?;
+
+                Ok(LoginResult::Success)
+            },
+        }
+    }
+
+    /// Submits the answer to a captcha shown on the login form. Steam may come back with another
+    /// captcha, a Steam Guard prompt or any other login result.
+    pub async fn provide_captcha(&mut self, answer: String) -> Result<LoginResult> {
+        if self.state() != LoginState::AwaitingCaptcha {
+            return Err(anyhow!("not waiting for a captcha answer"));
         }
 
-        let username_input = username_input.unwrap();
-        username_input.click()?;
-        tab.type_str(&self.steam_username)?;
+        let res = match self.browser.submit_captcha(&answer, &self.steam_password).await {
+            Ok(page) => self.handle_login_page(page).await,
+            Err(why) => Err(why),
+        };
+        if let Err(why) = &res {
+            self.fail(why).await;
+        }
+        res
+    }
 
-        tab.wait_for_element("input#password")?.click()?;
-        tab.type_str(&self.steam_password)?.press_key("Enter")?;
+    async fn login_rejected(&mut self, res: LoginResult) -> Result<LoginResult> {
+        self.browser.close().await?;
+        self.transition(LoginState::Failed(res.failure_reason().unwrap_or_default().to_string()))?;
+        Ok(res)
+    }
 
-        time::sleep(Duration::from_secs(10)).await;
+    /// Polls a pending QR login once. Returns `true` once it was approved in the mobile app and
+    /// the partner session works.
+    pub async fn poll_qr_login(&mut self, challenge: &mut QrChallenge) -> Result<bool> {
+        if self.state() != LoginState::AwaitingQrApproval {
+            return Err(anyhow!("not waiting for a QR login approval"));
+        }
 
-        let png = tab.capture_screenshot(CaptureScreenshotFormatOption::Png, Some(100), None, false)?;
-        let mut file = File::create("screenshot.png")?;
-        file.write_all(&png)?;
+        let res = self.try_poll_qr_login(challenge).await;
+        if let Err(why) = &res {
+            self.fail(why).await;
+        }
+        res
+    }
 
+    async fn try_poll_qr_login(&mut self, challenge: &mut QrChallenge) -> Result<bool> {
+        let client = self.client.clone().ok_or_else(|| anyhow!("client not initialized"))?;
 
-        let auth_el = tab.wait_for_element("input#authcode");
+        let refresh_token = match qr_login::poll(&client, challenge).await? {
+            Some(token) => token,
+            None => return Ok(false),
+        };
 
-        // let val = tab.wait_for_element("body")?
-        //     .call_js_fn("function() { return this.innerHTML; }", vec![], false)?
-        //     .value.unwrap();
-        //
-        // let mut file = File::create("login.html")?;
-        // file.write_all(val.as_str().unwrap_or("").as_bytes())?;
+        qr_login::finalize(&client, &refresh_token).await?;
+        self.check_if_logged_in().await
+            .map_err(|_| anyhow!("QR login was approved but the partner site didn't accept the session"))?;
 
+        self.transition(LoginState::LoggedIn)?;
+        Ok(true)
+    }
 
+    /// Abandons a login waiting for a Steam Guard code or QR approval and closes the browser.
+    pub async fn cancel_login(&mut self) -> Result<()> {
+        self.browser.close().await?;
+        if self.state().is_login_in_progress() {
+            self.transition(LoginState::LoggedOut)?;
+        }
+        Ok(())
+    }
 
-        if auth_el.is_ok() {
-            return Ok(LoginResult::AuthCodeNeeded);
+    /// Lightweight check that the session is still valid: Steam redirects to the login page
+    /// when it isn't.
+    pub async fn probe_session(&mut self) -> Result<bool> {
+        let client = self.client.clone().ok_or(ScrapeError::NotLoggedIn)?;
+        let res = client.get(&self.session_probe_url).send().await
+            .and_then(|res| res.error_for_status())
+            .map_err(ScrapeError::from)?;
+
+        let valid = !res.url().path().starts_with("/login");
+        if valid {
+            self.last_authenticated_fetch = Some(Utc::now());
+        } else if self.state() == LoginState::LoggedIn {
+            self.transition(LoginState::Expired)?;
         }
 
-        tab.wait_until_navigated()?;
-        self.save_cookies()?;
+        Ok(valid)
+    }
 
-        self.is_logged_in = true;
-        self.client = Some(Arc::new(self.get_client()?));
-        self.close()?;
+    pub fn session_expiry(&self) -> Option<DateTime<Utc>> {
+        let expires = self.cookie_store.expiry("steamLoginSecure")?;
+        Some(DateTime::from_utc(NaiveDateTime::from_timestamp(expires as i64, 0), Utc))
+    }
 
-        Ok(LoginResult::Success)
+    pub fn last_authenticated_fetch(&self) -> Option<DateTime<Utc>> {
+        self.last_authenticated_fetch
     }
 
     async fn check_if_logged_in(&self) -> Result<()> {
@@ -171,86 +403,127 @@ impl Scrapper {
         let document = &Html::parse_document(&text);
         let title = document.get_element_text("head title")?;
         if title != "Game: Decorporation" {
-            return Err(anyhow!("Login failed"));
+            return Err(ScrapeError::NotLoggedIn.into());
         }
 
         Ok(())
     }
 
-    pub fn provide_auth_code(&mut self, auth_code: String) -> Result<()> {
-        let tab = self.tab.clone().unwrap();
-
-        let auth_el = tab.wait_for_element("input#authcode")?;
-
-        auth_el.click()?;
-
-        tab.type_str(auth_code.trim())?;
-
-        tab.wait_for_element("input#friendlyname")?.click()?;
-        tab.type_str("Decorporation bot")?;
-
+    /// Submits a Steam Guard code. On `WrongCode` the page is reset for another attempt and the
+    /// state stays `AwaitingSteamGuard`.
+    pub async fn provide_auth_code(&mut self, auth_code: String) -> Result<AuthCodeResult> {
+        if self.state() != LoginState::AwaitingSteamGuard {
+            return Err(anyhow!("not waiting for a Steam Guard code"));
+        }
 
-        tab.wait_for_element("#auth_buttonset_entercode > div.auth_button.leftbtn")?.click()?;
-        println!("clicked submit btn");
-        tab.wait_for_element("#success_continue_btn")?.click()?;
+        let res = self.try_provide_auth_code(auth_code).await;
+        if let Err(why) = &res {
+            self.fail(why).await;
+        }
+        res
+    }
 
-        tab.wait_until_navigated()?;
-        self.save_cookies()?;
+    async fn try_provide_auth_code(&mut self, auth_code: String) -> Result<AuthCodeResult> {
+        let cookies = match self.browser.submit_auth_code(&auth_code).await? {
+            AuthCodePage::Accepted(cookies) => cookies,
+            AuthCodePage::WrongCode => return Ok(AuthCodeResult::WrongCode),
+        };
+        self.cookie_store.replace_from_browser(&cookies)?;
 
-        self.is_logged_in = true;
+        self.transition(LoginState::LoggedIn)?;
         self.client = Some(Arc::new(self.get_client()?));
 
-        self.close()?;
+        self.browser.close().await?;
 
-        Ok(())
+        Ok(AuthCodeResult::Success)
     }
 
     fn get_client(&self) -> Result<reqwest::Client> {
-        let jar = self.get_reqwest_cookies()?;
-        let arc = Arc::new(jar);
-        let client = reqwest::Client::builder().cookie_provider(arc).build()?;
+        let client = reqwest::Client::builder().cookie_provider(self.cookie_store.clone()).build()?;
         Ok(client)
     }
 
     async fn get_stats_text(&self) -> Result<String> {
-        let text = self.client.clone()
-            .ok_or_else(|| anyhow!("client not initialized"))?
-            .get(&self.stats_url)
-            .send()
-            .await?
-            .text()
-            .await?;
+        self.get_page_text(&self.stats_url).await
+    }
+
+    async fn get_page_text(&self, url: &str) -> Result<String> {
+        let client = self.client.clone().ok_or(ScrapeError::NotLoggedIn)?;
+        let text = async { client.get(url).send().await?.text().await }.await
+            .map_err(ScrapeError::from)?;
         Ok(text)
     }
 
     pub async fn get_stats(&mut self) -> Result<Stats> {
-        if !self.is_logged_in {
-            if let LoginResult::AuthCodeNeeded = self.login().await? {
-                return Err(anyhow!("not logged in"));
+        let span = info_span!("scrape");
+        let start = Instant::now();
+        let res = self.fetch_stats().instrument(span.clone()).await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+
+        span.in_scope(|| match &res {
+            Ok(_) => info!(duration_ms, outcome = "ok", "scrape finished"),
+            Err(why) => warn!(duration_ms, outcome = "error", "scrape failed: {:#}", why),
+        });
+        self.status.lock().unwrap().record_scrape(res.as_ref().map(|_| ()).map_err(|why| why.to_string()));
+        res
+    }
+
+    async fn fetch_stats(&mut self) -> Result<Stats> {
+        if self.state() != LoginState::LoggedIn {
+            let res = self.login("scrape").await?;
+            if res != LoginResult::Success {
+                // nobody is waiting on a prompt raised from a scrape, and leaving the login
+                // half done would block every later command
+                if let Err(why) = self.cancel_login().await {
+                    warn!("failed to cancel login after scrape: {:?}", why);
+                }
+            }
+            match res {
+                LoginResult::Success => {},
+                LoginResult::RateLimited(_) => return Err(ScrapeError::RateLimited.into()),
+                LoginResult::WrongPassword(_) => return Err(ScrapeError::NotLoggedIn.into()),
+                _ => return Err(ScrapeError::SteamGuardRequired.into()),
             }
         }
 
         let text = self.get_stats_text().await?;
         let document = &Html::parse_document(&text);
 
-        let title = document.get_element_text("head title")?;
+        let title = document.get_element_text("head title")
+            .map_err(|_| ScrapeError::LayoutChanged { field: "page title".to_string() })?;
         if title != "Game: Decorporation" {
-            return Err(anyhow!("not logged in!"));
+            self.transition(LoginState::Expired)?;
+            return Err(ScrapeError::SessionExpired.into());
         }
 
         let mut res = Stats{
-            gross_revenue: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(1) > td:nth-child(2)")?,
-            net_revenue: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(2) > td:nth-child(2)")?,
-            total_units: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(6) > td:nth-child(2)")?.atoi()?,
-            steam_units: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(4) > td:nth-child(2)")?.atoi()?,
-            units_returned: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(7) > td:nth-child(2)")?.atoi()?,
+            gross_revenue: summary_text(document, "gross revenue", 1)?,
+            net_revenue: summary_text(document, "net revenue", 2)?,
+            total_units: summary_int(document, "total units", 6)?,
+            steam_units: summary_int(document, "Steam units", 4)?,
+            units_returned: summary_int(document, "units returned", 7)?,
             return_percent: Percent(0.0),
-            current_players: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(9) > td:nth-child(2)")?.atoi()?,
-            daily_active_users: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(10) > td:nth-child(2)")?.atoi()?,
-            lifetime_unique_users: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(11) > td:nth-child(2)")?.atoi()?,
-            wishlist_count: document.get_element_text(r"#gameDataLeft > div.lifetimeSummaryCtn > table > tbody > tr:nth-child(14) > td:nth-child(2)")?.trim().to_string().atoi()?,
+            current_players: summary_int(document, "current players", 9)?,
+            daily_active_users: summary_int(document, "daily active users", 10)?,
+            lifetime_unique_users: summary_int(document, "lifetime unique users", 11)?,
+            wishlist_count: summary_int(document, "wishlist count", 14)?,
+            key_units: 0,
+            store: StorePage::default(),
+            key_batches: BTreeMap::new(),
+            derived: DerivedMetrics::default(),
         };
+        res.key_units = res.total_units - res.steam_units;
         res.return_percent = Percent((res.units_returned as f32) / (-res.steam_units as f32));
+        res.store = self.get_store_page().await;
+
+        res.key_batches = self.get_key_batches().await;
+
+        let today = Utc::now().naive_utc().date();
+        if self.day_start.as_ref().map_or(true, |(date, _)| *date != today) {
+            self.day_start = Some((today, res.clone()));
+        }
+        res.derived = DerivedMetrics::compute(&res, &self.day_start.as_ref().unwrap().1)?;
+        self.last_authenticated_fetch = Some(Utc::now());
 
         Ok(res)
 
@@ -266,96 +539,72 @@ impl Scrapper {
 
 
 
-    fn load_cookies_from_file(&self) -> Result<Vec<CookieParam>> {
-        let res = fs::read_to_string(&self.cookies_path);
+    /// Store page metadata is best-effort: on failure the last known page is reused so a flaky
+    /// store API doesn't show up as a change.
+    async fn get_store_page(&mut self) -> StorePage {
+        if self.app_id == 0 {
+            return StorePage::default();
+        }
 
-        if let Err(why) = res {
-            return if why.kind() == ErrorKind::NotFound {
-                Ok(vec![])
-            } else {
-                Err(anyhow!(why))
+        let client = self.client.clone().unwrap_or_default();
+        match get_store_page(&client, &self.store_url, &self.community_url, self.app_id).await {
+            Ok(page) => {
+                self.last_store_page = Some(page.clone());
+                page
+            },
+            Err(why) => {
+                warn!("failed to get store page: {:?}", why);
+                self.last_store_page.clone().unwrap_or_default()
             }
         }
-
-        let str = res.unwrap();
-
-        let cookies: Vec<Cookie> = serde_json::from_str(&str)?;
-        let res = cookies.iter().map(|c| CookieParam{
-            name: c.name.clone(),
-            value: c.value.clone(),
-            url: None,
-            domain: c.domain.clone().into(),
-            path: c.path.clone().into(),
-            expires: c.expires.into(),
-            priority: None,
-            same_party: None,
-            source_scheme: None,
-            source_port: None,
-            http_only: c.http_only.into(),
-            secure: c.secure.into(),
-            same_site: c.same_site.clone(),
-            partition_key: None
-        }).collect::<Vec<_>>();
-        Ok(res)
     }
 
-    fn load_cookies(&self) -> Result<()> {
-        if self.tab.is_none() {
-            return Err(anyhow!("not logged in!"));
+    /// Best-effort like the store page, the previous batches are kept if the report can't be read.
+    async fn get_key_batches(&mut self) -> BTreeMap<String, i32> {
+        if self.key_report_url.is_empty() {
+            return BTreeMap::new();
         }
-        self.tab.clone().unwrap().set_cookies(self.load_cookies_from_file()?)?;
-        Ok(())
-    }
 
-    fn save_cookies(&self) -> Result<()> {
-        let cookies = self.tab.clone().unwrap().get_cookies()?;
-        let mut file = File::create(&self.cookies_path)?;
-        file.write_all(&serde_json::to_vec(&cookies)?)?;
-        Ok(())
-    }
-
-    fn get_reqwest_cookies(&self) -> Result<reqwest::cookie::Jar> {
-        let jar = reqwest::cookie::Jar::default();
-        let url = reqwest::Url::parse("https://partner.steampowered.com")?;
-
-        let cookies = self.load_cookies_from_file()?;
-        for c in cookies {
-            jar.add_cookie_str(
-                &cookie::Cookie::build(c.name, c.value).domain("partner.steampowered.com").finish().to_string(),
-                &url);
+        match self.get_page_text(&self.key_report_url).await.and_then(|text| parse_key_report(&text)) {
+            Ok(batches) => {
+                self.last_key_batches = batches.clone();
+                batches
+            },
+            Err(why) => {
+                warn!("failed to get key report: {:?}", why);
+                self.last_key_batches.clone()
+            }
         }
-
-        Ok(jar)
     }
 
-    pub fn close(&mut self) -> Result<()> {
-        if let Some(tab) = self.tab.clone() {
-            tab.close(true)?;
+    /// Screenshots one of the `SCREENSHOT_PAGES` with the saved session, clipped to the element
+    /// matching `clip` if given.
+    pub async fn screenshot(&mut self, page: &str, clip: Option<String>) -> Result<Vec<u8>> {
+        if self.state() != LoginState::LoggedIn {
+            return Err(ScrapeError::NotLoggedIn.into());
         }
-        self.browser = None;
-        self.tab = None;
-        Ok(())
-    }
 
-    pub fn logout(&mut self) -> Result<()> {
-        // if Path::new(&self.cookies_path).exists() {
-        //     fs::remove_file(&self.cookies_path)?;
-        // }
+        let url = match page {
+            "app" => self.stats_url.clone(),
+            _ if self.app_id == 0 => return Err(anyhow!("`app_id` is not set")),
+            "regional" => format!("{}/region/?appID={}", PARTNER_URL, self.app_id),
+            "wishlist" => format!("{}/app/wishlist/{}/", PARTNER_URL, self.app_id),
+            other => return Err(anyhow!("unknown page `{}`, try one of: {}", other, SCREENSHOT_PAGES.join(", "))),
+        };
 
-        if let Some(tab) = self.tab.clone() {
-            if let Ok(cookies) = tab.get_cookies() {
-                tab.delete_cookies(cookies.iter().map(|c| DeleteCookies {
-                    name: c.name.clone(),
-                    domain: Some(c.domain.clone()),
-                    path: None,
-                    url: None
-                }).collect())?;
-            }
-        }
+        let res = self.browser.screenshot(&url, self.cookie_store.to_cookie_params(), clip).await;
+        self.browser.close().await?;
+        res
+    }
 
-        self.close()?;
+    pub async fn logout(&mut self) -> Result<()> {
+        self.browser.logout().await?;
+        // the saved steamLoginSecure would otherwise keep the session alive, and the next scrape
+        // would log straight back in with it
+        self.cookie_store.clear()?;
+        self.client = None;
+        self.transition(LoginState::LoggedOut)?;
 
         Ok(())
     }
 }
-
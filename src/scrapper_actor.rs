@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task;
+use tracing::{Instrument, Span};
+
+use crate::login_state::ScrapperStatus;
+use crate::qr_login::QrChallenge;
+use crate::scrape_error::ScrapeError;
+use crate::scrapper::{AuthCodeResult, LoginResult, Scrapper, Stats};
+
+enum Command {
+    Login(String, oneshot::Sender<Result<LoginResult>>),
+    ProvideAuthCode(String, oneshot::Sender<Result<AuthCodeResult>>),
+    ProvideCaptcha(String, oneshot::Sender<Result<LoginResult>>),
+    PollQrLogin(QrChallenge, oneshot::Sender<Result<(bool, QrChallenge)>>),
+    CancelLogin(oneshot::Sender<Result<()>>),
+    Logout(oneshot::Sender<Result<()>>),
+    GetStats(oneshot::Sender<Result<Stats, Arc<anyhow::Error>>>),
+    ProbeSession(oneshot::Sender<Result<bool>>),
+    Screenshot(String, Option<String>, oneshot::Sender<Result<Vec<u8>>>),
+}
+
+/// What the scrapper knew after its last command, readable without waiting on the actor.
+#[derive(Clone, Default)]
+pub struct ScrapperSnapshot {
+    pub stats: Option<(DateTime<Utc>, Stats)>,
+    pub session_expiry: Option<DateTime<Utc>>,
+    pub last_authenticated_fetch: Option<DateTime<Utc>>,
+}
+
+/// Cheap to clone handle to the scrapper task. The scrapper itself is owned by a single task
+/// that works through a queue, so a slow login no longer holds a lock everyone else waits on.
+#[derive(Clone)]
+pub struct ScrapperHandle {
+    /// Each command goes with the caller's span, so what the scrapper logs for it shows up
+    /// under the command that asked.
+    tx: mpsc::Sender<(Span, Command)>,
+    snapshot: watch::Receiver<ScrapperSnapshot>,
+    status: Arc<Mutex<ScrapperStatus>>,
+}
+
+impl ScrapperHandle {
+    pub fn spawn(scrapper: Scrapper) -> Self {
+        let (tx, rx) = mpsc::channel(32);
+        let (snapshot_tx, snapshot) = watch::channel(ScrapperSnapshot::default());
+        let status = scrapper.status();
+
+        task::spawn(run(scrapper, rx, snapshot_tx));
+
+        ScrapperHandle { tx, snapshot, status }
+    }
+
+    pub fn status(&self) -> Arc<Mutex<ScrapperStatus>> {
+        self.status.clone()
+    }
+
+    pub fn snapshot(&self) -> ScrapperSnapshot {
+        self.snapshot.borrow().clone()
+    }
+
+    pub fn login_in_progress(&self) -> bool {
+        self.status.lock().unwrap().state().is_login_in_progress()
+    }
+
+    /// Fails right away while a login is running instead of queueing behind it. The error is
+    /// transient, so the interval skips the tick rather than stopping.
+    fn ensure_idle(&self) -> Result<()> {
+        if self.login_in_progress() {
+            return Err(ScrapeError::LoginInProgress.into());
+        }
+        Ok(())
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send((Span::current(), command(tx))).await.map_err(|_| anyhow!("scrapper task stopped"))?;
+        rx.await.map_err(|_| anyhow!("scrapper task stopped"))
+    }
+
+    pub async fn login(&self, user: &str) -> Result<LoginResult> {
+        if self.login_in_progress() {
+            return Err(anyhow!("login already in progress"));
+        }
+        self.request(|tx| Command::Login(user.to_string(), tx)).await?
+    }
+
+    pub async fn provide_auth_code(&self, auth_code: String) -> Result<AuthCodeResult> {
+        self.request(|tx| Command::ProvideAuthCode(auth_code, tx)).await?
+    }
+
+    pub async fn provide_captcha(&self, answer: String) -> Result<LoginResult> {
+        self.request(|tx| Command::ProvideCaptcha(answer, tx)).await?
+    }
+
+    pub async fn poll_qr_login(&self, challenge: &mut QrChallenge) -> Result<bool> {
+        let (done, updated) = self.request(|tx| Command::PollQrLogin(challenge.clone(), tx)).await??;
+        *challenge = updated;
+        Ok(done)
+    }
+
+    pub async fn cancel_login(&self) -> Result<()> {
+        self.request(Command::CancelLogin).await?
+    }
+
+    /// Not gated on `ensure_idle`, logging out also abandons a login that's waiting on someone.
+    pub async fn logout(&self) -> Result<()> {
+        self.request(Command::Logout).await?
+    }
+
+    pub async fn get_stats(&self) -> Result<Stats> {
+        self.ensure_idle()?;
+        // the error is shared between deduplicated requests, so it's rebuilt for each caller
+        self.request(Command::GetStats).await?.map_err(|why| match ScrapeError::find(&why) {
+            Some(e) => e.clone().into(),
+            None => anyhow!("{:#}", why),
+        })
+    }
+
+    /// The latest stats if they are younger than `max_age`, otherwise a live fetch. Returns when
+    /// the stats were scraped.
+    pub async fn stats_within(&self, max_age: chrono::Duration) -> Result<(DateTime<Utc>, Stats)> {
+        if let Some((at, stats)) = self.snapshot().stats {
+            if Utc::now() - at < max_age {
+                return Ok((at, stats));
+            }
+        }
+
+        let stats = self.get_stats().await?;
+        Ok((Utc::now(), stats))
+    }
+
+    pub async fn probe_session(&self) -> Result<bool> {
+        self.ensure_idle()?;
+        self.request(Command::ProbeSession).await?
+    }
+
+    pub async fn screenshot(&self, page: String, clip: Option<String>) -> Result<Vec<u8>> {
+        self.ensure_idle()?;
+        self.request(|tx| Command::Screenshot(page, clip, tx)).await?
+    }
+}
+
+async fn run(mut scrapper: Scrapper, mut rx: mpsc::Receiver<(Span, Command)>, snapshot_tx: watch::Sender<ScrapperSnapshot>) {
+    let mut snapshot = ScrapperSnapshot::default();
+    // commands pulled off the queue while collecting duplicate stats requests
+    let mut backlog = VecDeque::new();
+
+    loop {
+        let (span, command) = match backlog.pop_front() {
+            Some(command) => command,
+            None => match rx.recv().await {
+                Some(command) => command,
+                None => break,
+            },
+        };
+
+        // the receiving side may have given up waiting, so failed sends are ignored
+        let work = async {
+            match command {
+                Command::Login(user, reply) => {
+                    let _ = reply.send(scrapper.login(&user).await);
+                },
+                Command::ProvideAuthCode(auth_code, reply) => {
+                    let _ = reply.send(scrapper.provide_auth_code(auth_code).await);
+                },
+                Command::ProvideCaptcha(answer, reply) => {
+                    let _ = reply.send(scrapper.provide_captcha(answer).await);
+                },
+                Command::PollQrLogin(mut challenge, reply) => {
+                    let res = scrapper.poll_qr_login(&mut challenge).await;
+                    let _ = reply.send(res.map(|done| (done, challenge)));
+                },
+                Command::CancelLogin(reply) => {
+                    let _ = reply.send(scrapper.cancel_login().await);
+                },
+                Command::Logout(reply) => {
+                    let _ = reply.send(scrapper.logout().await);
+                },
+                Command::GetStats(reply) => {
+                    let res = scrapper.get_stats().await.map_err(Arc::new);
+
+                    // stats requests that queued up during the fetch share its result
+                    let mut waiters = vec![reply];
+                    while let Ok((span, command)) = rx.try_recv() {
+                        match command {
+                            Command::GetStats(reply) => waiters.push(reply),
+                            other => backlog.push_back((span, other)),
+                        }
+                    }
+
+                    if let Ok(stats) = &res {
+                        snapshot.stats = Some((Utc::now(), stats.clone()));
+                    }
+                    for waiter in waiters {
+                        let _ = waiter.send(res.clone());
+                    }
+                },
+                Command::ProbeSession(reply) => {
+                    let _ = reply.send(scrapper.probe_session().await);
+                },
+                Command::Screenshot(page, clip, reply) => {
+                    let _ = reply.send(scrapper.screenshot(&page, clip).await);
+                },
+            }
+        };
+        work.instrument(span).await;
+
+        snapshot.session_expiry = scrapper.session_expiry();
+        snapshot.last_authenticated_fetch = scrapper.last_authenticated_fetch();
+        let _ = snapshot_tx.send(snapshot.clone());
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serenity::http::Http;
+use serenity::model::id::UserId;
+use serenity::model::interactions::message_component::ButtonStyle;
+use tokio::{task, time};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::Config;
+use crate::scrape_error::ScrapeError;
+use crate::scrapper_actor::ScrapperHandle;
+
+pub const LOGIN_BUTTON_ID: &str = "start_login";
+
+pub async fn notify_owner(http: &Http, owner_id: u64, content: String) -> Result<()> {
+    let dm = UserId(owner_id).create_dm_channel(http).await?;
+
+    dm.send_message(http, |m| {
+        m.content(content).components(|c| c.create_action_row(|r| r.create_button(|b| {
+            b.style(ButtonStyle::Primary).label("Log in").custom_id(LOGIN_BUTTON_ID)
+        })))
+    }).await?;
+
+    Ok(())
+}
+
+/// Whether `new` changes anything a running session monitor was started with.
+pub fn settings_changed(old: &Config, new: &Config) -> bool {
+    old.owner_id != new.owner_id
+        || old.session_probe_interval_secs != new.session_probe_interval_secs
+        || old.session_expiry_warning_secs != new.session_expiry_warning_secs
+}
+
+/// Periodically probes the Steam session and DMs the owner before the login cookie expires, or
+/// as soon as the session is found to be invalid.
+pub fn start_session_monitor(cfg: Config, scrapper: ScrapperHandle, http: Arc<Http>) -> Option<JoinHandle<()>> {
+    if cfg.owner_id == 0 || cfg.session_probe_interval_secs == 0 {
+        return None;
+    }
+
+    let mut interval = time::interval(Duration::from_secs(cfg.session_probe_interval_secs));
+    let warning_lead = chrono::Duration::seconds(cfg.session_expiry_warning_secs as i64);
+
+    Some(task::spawn(async move {
+        let mut warned_expiry: Option<DateTime<Utc>> = None;
+        let mut invalid_notified = false;
+
+        loop {
+            interval.tick().await;
+
+            if scrapper.login_in_progress() {
+                continue;
+            }
+
+            let probe = scrapper.probe_session().await;
+            let snapshot = scrapper.snapshot();
+            let expiry = snapshot.session_expiry;
+            // tells the owner how long scraping has really been down
+            let last_fetch = match snapshot.last_authenticated_fetch {
+                Some(at) => format!(" The last authenticated fetch was at {}.", at.format("%Y-%m-%d %H:%M UTC")),
+                None => String::new(),
+            };
+
+            let msg = match probe {
+                Ok(false) if !invalid_notified => {
+                    invalid_notified = true;
+                    Some(format!("Steam session was invalidated, log in again to resume scraping.{}", last_fetch))
+                },
+                Ok(false) => None,
+                Ok(true) => {
+                    invalid_notified = false;
+                    match expiry {
+                        Some(expiry) if expiry - Utc::now() < warning_lead && warned_expiry != Some(expiry) => {
+                            warned_expiry = Some(expiry);
+                            Some(format!("Steam session expires at {}, log in again before then.{}", expiry.format("%Y-%m-%d %H:%M UTC"), last_fetch))
+                        },
+                        _ => None,
+                    }
+                },
+                // no session was ever established, e.g. the startup login needed a Steam Guard code
+                Err(why) if ScrapeError::find(&why) == Some(&ScrapeError::NotLoggedIn) => {
+                    if invalid_notified {
+                        None
+                    } else {
+                        invalid_notified = true;
+                        Some("Not logged in to Steam, log in to start scraping.".to_string())
+                    }
+                },
+                Err(why) => {
+                    warn!("session probe failed: {:?}", why);
+                    None
+                }
+            };
+
+            if let Some(msg) = msg {
+                if let Err(why) = notify_owner(&http, cfg.owner_id, msg).await {
+                    warn!("failed to notify owner: {:?}", why);
+                }
+            }
+        }
+    }))
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+static MEMBER_COUNT: Lazy<Regex> = Lazy::new(|| Regex::new(r"<memberCount>(\d+)</memberCount>").unwrap());
+static APP_TAG: Lazy<Selector> = Lazy::new(|| Selector::parse("#glanceCtnResponsiveRight a.app_tag").unwrap());
+
+#[derive(Debug, Default, PartialEq, PartialOrd, Clone, Serialize)]
+pub struct StorePage {
+    pub followers: i32,
+    pub price: String,
+    pub discount_percent: i32,
+    pub coming_soon: bool,
+    pub release_date: String,
+    pub tags: Vec<String>,
+}
+
+impl StorePage {
+    pub fn describe_price_change(&self, new: &StorePage) -> Option<String> {
+        // an empty price means the page was never fetched, not that the game went off sale
+        if self.price.is_empty() || (self.price == new.price && self.discount_percent == new.discount_percent) {
+            return None;
+        }
+
+        if new.discount_percent > 0 && self.discount_percent == 0 {
+            return Some(format!("Sale is live: **{}% off**, now {} (was {})", new.discount_percent, new.price, self.price));
+        }
+
+        Some(format!(
+            "Store price changed: {} ({}% off) -> {} ({}% off)",
+            self.price, self.discount_percent, new.price, new.discount_percent
+        ))
+    }
+}
+
+#[derive(Deserialize)]
+struct AppDetails {
+    success: bool,
+    data: Option<AppData>,
+}
+
+#[derive(Deserialize)]
+struct AppData {
+    #[serde(default)]
+    is_free: bool,
+    price_overview: Option<PriceOverview>,
+    release_date: Option<ReleaseDate>,
+}
+
+#[derive(Deserialize)]
+struct PriceOverview {
+    discount_percent: i32,
+    final_formatted: String,
+}
+
+#[derive(Deserialize)]
+struct ReleaseDate {
+    coming_soon: bool,
+    date: String,
+}
+
+async fn get_followers(client: &reqwest::Client, community_url: &str, app_id: u64) -> Result<i32> {
+    let text = client.get(format!("{}/games/{}/memberslistxml/?xml=1", community_url, app_id))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let count = MEMBER_COUNT.captures(&text)
+        .and_then(|c| c.get(1))
+        .ok_or_else(|| anyhow!("memberCount not found"))?
+        .as_str()
+        .parse()?;
+
+    Ok(count)
+}
+
+async fn get_app_data(client: &reqwest::Client, store_url: &str, app_id: u64) -> Result<AppData> {
+    let mut res = client.get(format!("{}/api/appdetails?appids={}", store_url, app_id))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<HashMap<String, AppDetails>>()
+        .await?;
+
+    let details = res.remove(&app_id.to_string()).ok_or_else(|| anyhow!("app {} missing from appdetails", app_id))?;
+    if !details.success {
+        return Err(anyhow!("appdetails returned success=false"));
+    }
+
+    details.data.ok_or_else(|| anyhow!("appdetails returned no data"))
+}
+
+/// User-applied tags, which appdetails doesn't return, in the order the store page shows them.
+async fn get_tags(client: &reqwest::Client, store_url: &str, app_id: u64) -> Result<Vec<String>> {
+    let text = client.get(format!("{}/app/{}/?l=english", store_url, app_id))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let document = Html::parse_document(&text);
+    let tags = document.select(&APP_TAG)
+        .map(|a| a.text().collect::<String>().trim().to_string())
+        .filter(|tag| !tag.is_empty() && tag != "+")
+        .collect();
+
+    Ok(tags)
+}
+
+pub async fn get_store_page(client: &reqwest::Client, store_url: &str, community_url: &str, app_id: u64) -> Result<StorePage> {
+    let followers = get_followers(client, community_url, app_id).await?;
+    let data = get_app_data(client, store_url, app_id).await?;
+    let tags = get_tags(client, store_url, app_id).await?;
+
+    let (price, discount_percent) = match data.price_overview {
+        Some(p) => (p.final_formatted, p.discount_percent),
+        None if data.is_free => ("Free".to_string(), 0),
+        None => (String::new(), 0),
+    };
+    let (coming_soon, release_date) = data.release_date.map_or((false, String::new()), |r| (r.coming_soon, r.date));
+
+    Ok(StorePage {
+        followers,
+        price,
+        discount_percent,
+        coming_soon,
+        release_date,
+        tags,
+    })
+}
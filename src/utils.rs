@@ -24,3 +24,64 @@ impl GetElementText for Html {
     }
 }
 
+
+pub trait ParseMoney {
+    fn parse_money(&self) -> Result<f32>;
+}
+
+impl ParseMoney for str {
+    fn parse_money(&self) -> Result<f32> {
+        let digits = self.chars().filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-').collect::<String>();
+        digits.parse::<f32>().map_err(|_| anyhow!("Could not convert {} to money", self))
+    }
+}
+
+/// Discord rejects messages longer than this many characters.
+pub const MESSAGE_LIMIT: usize = 2000;
+
+/// Room kept for the note about the lines `fit_lines` left out.
+const OMITTED_NOTE_LEN: usize = 32;
+
+/// Concatenates as many of `lines`, each ending in a newline, as fit in `budget` characters and
+/// notes how many were left out.
+pub fn fit_lines(lines: &[String], budget: usize) -> String {
+    if lines.iter().map(|l| l.chars().count()).sum::<usize>() <= budget {
+        return lines.concat();
+    }
+
+    let mut res = String::new();
+    let mut used = 0;
+    for (i, line) in lines.iter().enumerate() {
+        let len = line.chars().count();
+        if used + len > budget.saturating_sub(OMITTED_NOTE_LEN) {
+            res.push_str(&format!("... {} more line(s)\n", lines.len() - i));
+            break;
+        }
+        res.push_str(line);
+        used += len;
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("+line {}\n", i)).collect()
+    }
+
+    #[test]
+    fn lines_that_fit_are_kept_as_is() {
+        assert_eq!(fit_lines(&lines(3), 100), "+line 0\n+line 1\n+line 2\n");
+    }
+
+    #[test]
+    fn overflow_is_cut_with_a_note() {
+        let res = fit_lines(&lines(100), 100);
+
+        assert!(res.chars().count() <= 100, "{}", res);
+        assert!(res.starts_with("+line 0\n"));
+        assert!(res.ends_with("more line(s)\n"));
+    }
+}